@@ -5,3 +5,7 @@ cargo readme > README.md
 	cd silly-alloc-macros
 	cargo readme > README.md
 )
+(
+	cd silly-alloc-replay
+	cargo readme > README.md
+)
@@ -11,6 +11,7 @@ impl TryToIntLiteral for Expr {
     }
 }
 
+#[allow(dead_code)]
 pub trait TryToTypeExpression {
     fn try_to_type_expression(&self) -> Option<&ExprType>;
 }
@@ -2,7 +2,8 @@
 `silly_alloc_macros` is a macro support crate for the `silly_alloc` crate. Please see the documentation there.
 */
 use proc_macro2::{Span, TokenStream};
-use quote::{quote, spanned::Spanned};
+use proc_macro_crate::{crate_name, FoundCrate};
+use quote::{quote, quote_spanned, spanned::Spanned, ToTokens};
 use syn::{
     parse::{Parse, ParseStream},
     *,
@@ -10,10 +11,28 @@ use syn::{
 
 mod cast_helpers;
 use cast_helpers::*;
+mod size_classes;
+use size_classes::SizeClasses;
 
+const PACKAGE_NAME: &str = "silly-alloc";
 const CRATE_NAME: &str = "silly_alloc";
 
+/// The biggest alignment a bucket supports. Needs to be kept in sync with `silly_alloc::bucket::MAX_ALIGN`.
+const MAX_ALIGN: usize = 65536;
+
+/// Methods of the generated allocator, which would clash with a bucket’s accessor method.
+const RESERVED_BUCKET_NAMES: &[&str] = &[
+    "new",
+    "with_arena",
+    "stats",
+    "reset",
+    "reset_bucket",
+    "for_each_live",
+];
+
 struct BucketAllocatorDescriptor {
+    attrs: Vec<Attribute>,
+    vis: Visibility,
     name: Ident,
     buckets: Vec<BucketDescriptor>,
 }
@@ -25,15 +44,26 @@ impl Parse for BucketAllocatorDescriptor {
         let buckets: Vec<Result<BucketDescriptor>> =
             st.fields.iter().map(|field| field.try_into()).collect();
         let buckets: Vec<BucketDescriptor> = Result::from_iter(buckets)?;
-        Ok(BucketAllocatorDescriptor { name, buckets })
+        Ok(BucketAllocatorDescriptor {
+            attrs: st.attrs,
+            vis: st.vis,
+            name,
+            buckets,
+        })
     }
 }
 
 struct BucketDescriptor {
-    _name: Ident,
-    slot_size: usize,
-    align: usize,
-    num_slots: usize,
+    name: Ident,
+    /// Doc comments on the field, which are carried over to the bucket’s accessor method.
+    docs: Vec<Attribute>,
+    slot_size: BucketParam,
+    align: BucketParam,
+    num_slots: BucketParam,
+    /// Maximum number of consecutive slots a single allocation may occupy.
+    max_span: BucketParam,
+    /// Number of freed allocations that are held back before their slots become claimable again.
+    quarantine: BucketParam,
 }
 
 impl TryFrom<&Field> for BucketDescriptor {
@@ -43,6 +73,18 @@ impl TryFrom<&Field> for BucketDescriptor {
             .ident
             .as_ref()
             .ok_or(Error::new(field.__span(), "Struct field without a name."))?;
+        if RESERVED_BUCKET_NAMES
+            .iter()
+            .any(|reserved| name == reserved)
+        {
+            return Err(Error::new(
+                name.span(),
+                format!(
+                    "Bucket name '{}' is reserved for a method of the generated allocator.",
+                    name
+                ),
+            ));
+        }
 
         let Type::Path(path_type) = &field.ty else {
             return Err(Error::new(
@@ -64,9 +106,11 @@ impl TryFrom<&Field> for BucketDescriptor {
             ));
         }
 
-        let mut slot_size: Option<usize> = None;
-        let mut num_slots: Option<usize> = None;
-        let mut align: Option<usize> = None;
+        let mut slot_size: Option<(BucketParam, Span)> = None;
+        let mut num_slots: Option<(BucketParam, Span)> = None;
+        let mut align: Option<(BucketParam, Span)> = None;
+        let mut max_span: Option<(BucketParam, Span)> = None;
+        let mut quarantine: Option<(BucketParam, Span)> = None;
         let PathArguments::AngleBracketed(generics) = &path_seg.arguments else {
             return Err(Error::new(
                 path_seg.__span(),
@@ -101,17 +145,30 @@ impl TryFrom<&Field> for BucketDescriptor {
                 ));
             }
             let param_generic_arg = param_generic_args.args.iter().next().unwrap();
-            let GenericArgument::Const(expr) = param_generic_arg else {
-                return Err(Error::new(
-                    param_generic_arg.__span(),
-                    "Bucket parameters must be a const expr.",
-                ));
+            let expr = match param_generic_arg {
+                GenericArgument::Const(expr) => expr.clone(),
+                // A lone identifier like `NumSlots<MAX_ENTITIES>` is syntactically indistinguishable from a type, so it is parsed as one.
+                GenericArgument::Type(Type::Path(TypePath { qself: None, path })) => {
+                    Expr::Path(ExprPath {
+                        attrs: vec![],
+                        qself: None,
+                        path: path.clone(),
+                    })
+                }
+                _ => {
+                    return Err(Error::new(
+                        param_generic_arg.__span(),
+                        "Bucket parameters must be a const expr.",
+                    ))
+                }
             };
 
-            match param_name.to_string().as_str() {
-                "SlotSize" => slot_size = Some(expr_to_usize(expr)?),
-                "NumSlots" => num_slots = Some(expr_to_usize(expr)?),
-                "Align" => align = Some(expr_to_usize(expr)?),
+            let param = match param_name.to_string().as_str() {
+                "SlotSize" => &mut slot_size,
+                "NumSlots" => &mut num_slots,
+                "Align" => &mut align,
+                "MaxSpan" => &mut max_span,
+                "Quarantine" => &mut quarantine,
                 _ => {
                     return Err(Error::new(
                         name.__span(),
@@ -119,197 +176,985 @@ impl TryFrom<&Field> for BucketDescriptor {
                     ))
                 }
             };
+            if param.is_some() {
+                return Err(Error::new(
+                    param_type.__span(),
+                    format!("{} was specified more than once", param_name),
+                ));
+            }
+            *param = Some((BucketParam::from_expr(expr)?, param_generic_arg.__span()));
+        }
+
+        let (slot_size, slot_size_span) =
+            slot_size.ok_or(Error::new(generics.__span(), "SlotSize was not specified"))?;
+        let (num_slots, num_slots_span) =
+            num_slots.ok_or(Error::new(generics.__span(), "NumSlots was not specified"))?;
+        let (align, align_span) = align.unwrap_or((BucketParam::Literal(1), generics.__span()));
+        let (max_span, max_span_span) =
+            max_span.unwrap_or((BucketParam::Literal(1), generics.__span()));
+        let (quarantine, _) = quarantine.unwrap_or((BucketParam::Literal(0), generics.__span()));
+
+        if slot_size.as_literal() == Some(0) {
+            return Err(Error::new(slot_size_span, "SlotSize must not be zero"));
+        }
+        if num_slots.as_literal() == Some(0) {
+            return Err(Error::new(num_slots_span, "NumSlots must not be zero"));
+        }
+        if max_span.as_literal() == Some(0) {
+            return Err(Error::new(max_span_span, "MaxSpan must not be zero"));
+        }
+        if let Some(align) = align.as_literal() {
+            if !align.is_power_of_two() {
+                return Err(Error::new(align_span, "Align must be a power of two"));
+            }
+            if align > MAX_ALIGN {
+                return Err(Error::new(
+                    align_span,
+                    format!("Align must be at most {}", MAX_ALIGN),
+                ));
+            }
+        }
+        if let (Some(size), Some(align)) = (slot_size.as_literal(), align.as_literal()) {
+            if size % align != 0 {
+                return Err(Error::new(
+                    slot_size_span,
+                    format!("SlotSize must be a multiple of Align ({})", align),
+                ));
+            }
         }
 
         Ok(BucketDescriptor {
-            _name: name.clone(),
-            slot_size: slot_size
-                .ok_or(Error::new(generics.__span(), "SlotSlize was not specified"))?,
-            num_slots: num_slots
-                .ok_or(Error::new(generics.__span(), "NumSlots was not specified"))?,
-            align: align.unwrap_or(1),
+            name: name.clone(),
+            docs: field
+                .attrs
+                .iter()
+                .filter(|attr| attr.path.is_ident("doc"))
+                .cloned()
+                .collect(),
+            slot_size,
+            num_slots,
+            align,
+            max_span,
+            quarantine,
         })
     }
 }
 
-fn expr_to_usize(expr: &Expr) -> Result<usize> {
-    expr.try_to_int_literal()
-        .ok_or_else(|| Error::new(expr.__span(), "Bucket parameter must be an integer"))?
-        .parse::<usize>()
-        .map_err(|err| Error::new(expr.__span(), format!("{}", err)))
+/// The value of a bucket parameter.
+///
+/// Integer literals are known to the macro, which allows it to e.g. sort buckets. Any other const expression is passed through and only evaluated by the compiler.
+#[derive(Clone)]
+enum BucketParam {
+    Literal(usize),
+    Expr(Box<Expr>),
 }
 
-// This function exists because sometimes the macro needs to emit `crate::bucket::BucketImpl` and sometimes just `silly_alloc::bucket::BucketImpl`. In the in-crate tests, `crate::`... is needed, but for the doc tests and any other external package, `silly_alloc::` is needed. To distinguish which to emit, we inspect the `CARGO_CRATE_NAME` env variable. If it’s "silly_alloc", someone is doing development on the crate itself and running the tests, so `crate::` is used. The only exception are the doc tests, where annoyingly `CARGO_CRATE_NAME` is set to "silly_alloc", but the doc tests are compiled like an external piece of code that is linked against the `silly_alloc` crate. For a lack of a better solution, an additional env variable `SILLY_ALLOC_DOC_TESTS` is checked to override that behavior.
-fn crate_path() -> Ident {
-    fn crate_name_option() -> Option<Ident> {
-        if std::env::var("SILLY_ALLOC_DOC_TESTS").is_ok() {
-            return None;
+impl BucketParam {
+    fn from_expr(expr: Expr) -> Result<Self> {
+        // `Align<{ N }>` needs braces to be valid Rust, but the macro adds its own where they are needed.
+        let expr = match expr {
+            Expr::Block(ExprBlock {
+                ref attrs,
+                label: None,
+                ref block,
+            }) if attrs.is_empty() && block.stmts.len() == 1 => match &block.stmts[0] {
+                Stmt::Expr(inner) => inner.clone(),
+                _ => expr,
+            },
+            expr => expr,
+        };
+        let Some(lit) = expr.try_to_int_literal() else {
+            return Ok(BucketParam::Expr(Box::new(expr)));
+        };
+        lit.parse::<usize>()
+            .map(BucketParam::Literal)
+            .map_err(|err| Error::new(expr.__span(), format!("{}", err)))
+    }
+
+    fn as_literal(&self) -> Option<usize> {
+        match self {
+            BucketParam::Literal(v) => Some(*v),
+            BucketParam::Expr(_) => None,
         }
-        let pkg_name = std::env::var("CARGO_CRATE_NAME").ok()?;
-        if pkg_name == CRATE_NAME {
-            return Some(Ident::new("crate", Span::call_site()));
+    }
+}
+
+impl ToTokens for BucketParam {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        match self {
+            BucketParam::Literal(v) => {
+                LitInt::new(&v.to_string(), Span::call_site()).to_tokens(tokens)
+            }
+            // Paths can be used both as a value and as a const generic argument. Anything else needs to be wrapped in a block.
+            BucketParam::Expr(expr) => match expr.as_ref() {
+                Expr::Path(_) => expr.to_tokens(tokens),
+                _ => quote! { { #expr } }.to_tokens(tokens),
+            },
         }
-        None
     }
-    crate_name_option().unwrap_or_else(|| Ident::new(CRATE_NAME, Span::call_site()))
+}
+
+// The generated code refers to items from `silly_alloc`, which the caller might have renamed in their `Cargo.toml`, so the name is looked up there. `silly_alloc` declares `extern crate self as silly_alloc`, so that `::silly_alloc` works in its own tests and doc tests just like in any other crate. If `silly_alloc` is not a direct dependency, e.g. because the macro is re-exported from another crate, the caller has to provide the path with the `crate` option.
+fn default_crate_path() -> Path {
+    let name = match crate_name(PACKAGE_NAME) {
+        Ok(FoundCrate::Name(name)) => name,
+        Ok(FoundCrate::Itself) | Err(_) => CRATE_NAME.to_string(),
+    };
+    let ident = Ident::new(&name, Span::call_site());
+    parse_quote!(::#ident)
 }
 
 impl BucketDescriptor {
-    fn num_segments(&self) -> usize {
-        ((self.num_slots as f32) / 32.0).ceil() as usize
+    /// Buckets are sorted by slot size, then alignment. Only possible if both are literals.
+    fn sort_key(&self) -> Option<(usize, usize)> {
+        Some((self.slot_size.as_literal()?, self.align.as_literal()?))
+    }
+
+    /// Whether allocations can span multiple slots.
+    fn has_spans(&self) -> bool {
+        self.max_span.as_literal() != Some(1)
+    }
+
+    /// Whether freed slots are quarantined.
+    fn has_quarantine(&self) -> bool {
+        self.quarantine.as_literal() != Some(0)
+    }
+
+    /// The biggest allocation the bucket can serve and the bucket’s alignment. Only possible if all of them are literals.
+    fn max_size_and_align(&self) -> Option<(usize, usize)> {
+        let (slot_size, align) = self.sort_key()?;
+        Some((slot_size * self.max_span.as_literal()?, align))
+    }
+
+    /// Number of slots an allocation of `size` bytes occupies.
+    fn num_slots_for_size(&self) -> TokenStream {
+        let slot_size = &self.slot_size;
+        if self.has_spans() {
+            quote! { size.div_ceil(#slot_size).max(1) }
+        } else {
+            quote! { 1 }
+        }
+    }
+
+    fn num_segments(&self, opts: &BucketAllocatorOptions) -> TokenStream {
+        let num_slots = &self.num_slots;
+        let crate_path = &opts.crate_path;
+        quote! {
+            { #crate_path::bucket::num_segments_for_slots(#num_slots) }
+        }
+    }
+
+    fn bucket_type_ident(opts: &BucketAllocatorOptions) -> Ident {
+        let name = if opts.arena.is_some() {
+            "ArenaBucketImpl"
+        } else {
+            "BucketImpl"
+        };
+        Ident::new(name, Span::call_site())
+    }
+
+    fn as_init_values(&self, opts: &BucketAllocatorOptions) -> TokenStream {
+        let crate_path = &opts.crate_path;
+        let bucket_type_ident = Self::bucket_type_ident(opts);
+        quote! {
+            ::core::cell::UnsafeCell::new(#crate_path::bucket::#bucket_type_ident::new())
+        }
     }
 
-    fn as_init_values(&self) -> TokenStream {
-        let crate_path = crate_path();
+    fn as_struct_fields(&self, opts: &BucketAllocatorOptions) -> TokenStream {
+        let bucket_type = self.as_bucket_type(opts);
         quote! {
-            ::core::cell::UnsafeCell::new(#crate_path::bucket::BucketImpl::new())
+            ::core::cell::UnsafeCell<#bucket_type>
         }
     }
 
-    fn as_struct_fields(&self) -> TokenStream {
+    fn as_bucket_type(&self, opts: &BucketAllocatorOptions) -> TokenStream {
         let BucketDescriptor {
-            slot_size, align, ..
+            slot_size,
+            align,
+            quarantine,
+            ..
+        } = self;
+        let num_segments = self.num_segments(opts);
+        let crate_path = &opts.crate_path;
+        let bucket_type_ident = Self::bucket_type_ident(opts);
+        // Buckets without a quarantine keep the default, which takes up no space.
+        let quarantine = if self.has_quarantine() {
+            quote! { , #crate_path::bucket::QuarantineFifo<#quarantine> }
+        } else {
+            quote! {}
+        };
+        // Unsupported alignments are rejected by the type system, so the error should point at the alignment.
+        let span = match align {
+            BucketParam::Expr(expr) => expr.__span(),
+            BucketParam::Literal(_) => Span::call_site(),
+        };
+        quote_spanned! {span=>
+            #crate_path::bucket::#bucket_type_ident<#crate_path::bucket::SlotFor<#align, #slot_size>, #num_segments #quarantine>
+        }
+    }
+
+    fn as_accessor(
+        &self,
+        idx: usize,
+        vis: &Visibility,
+        opts: &BucketAllocatorOptions,
+    ) -> TokenStream {
+        let BucketDescriptor { name, docs, .. } = self;
+        let idx_key = Index::from(idx);
+        let bucket_type = self.as_bucket_type(opts);
+        let crate_path = &opts.crate_path;
+        quote! {
+            #(#docs)*
+            #[allow(dead_code)]
+            #vis fn #name(&self) -> #crate_path::bucket::BucketView<'_, #bucket_type> {
+                // The bucket is only ever mutated by the allocator itself.
+                unsafe { #crate_path::bucket::BucketView::new(&self.#idx_key) }
+            }
+        }
+    }
+
+    fn as_stats_init_value(&self, opts: &BucketAllocatorOptions) -> TokenStream {
+        let BucketDescriptor {
+            name,
+            slot_size,
+            align,
+            ..
         } = self;
-        let num_segments = self.num_segments();
-        let slot_type_ident = Ident::new(&format!("SlotWithAlign{}", align), align.__span());
-        let crate_path = crate_path();
+        let name = name.to_string();
+        let crate_path = &opts.crate_path;
+        let num_segments = self.num_segments(opts);
+        let capacity = quote! { #num_segments * #crate_path::bucket::NUM_SLOTS_PER_SEGMENT };
         quote! {
-            ::core::cell::UnsafeCell<#crate_path::bucket::BucketImpl<#crate_path::bucket::#slot_type_ident<#slot_size>, #num_segments>>
+            #crate_path::bucket::BucketStats::new(#name, #slot_size, #align, #capacity)
         }
     }
 
-    fn as_alloc_bucket_selectors(&self, idx: usize) -> TokenStream {
+    fn as_alloc_bucket_selectors(
+        &self,
+        idx: usize,
+        extra_fields: &ExtraFields,
+        opts: &BucketAllocatorOptions,
+    ) -> TokenStream {
         let BucketDescriptor {
             slot_size, align, ..
         } = self;
         let idx_key = Index::from(idx);
+        let claim_args = match &extra_fields.arena {
+            Some(arena_key) => quote! { &self.#arena_key },
+            None => quote! {},
+        };
+        // Only an allocation that moves on to a later bucket because this one is full counts as a fall-through. Without spilling, it fails instead. A missed search for a slot with a bigger alignment than the bucket’s doesn’t mean the bucket is full.
+        let fall_through = match (opts.spill, opts.aligned_slot_search) {
+            (false, _) => quote! {},
+            (true, false) => quote! { stats[#idx].record_fall_through(); },
+            (true, true) => quote! {
+                if align <= #align {
+                    stats[#idx].record_fall_through();
+                }
+            },
+        };
+        let (record_alloc, record_fall_through) = match &extra_fields.stats {
+            Some(_) => (
+                quote! { stats[#idx].record_alloc(size, num_slots); },
+                quote! {
+                    #fall_through
+                    first_candidate.get_or_insert(#idx);
+                },
+            ),
+            None => (quote! {}, quote! {}),
+        };
+        let stop_selection = if opts.spill {
+            quote! {}
+        } else {
+            quote! { break; }
+        };
+        let num_slots = self.num_slots_for_size();
+        let (max_size, claim) = if self.has_spans() {
+            let max_span = &self.max_span;
+            (
+                quote! { #slot_size * #max_span },
+                quote! {
+                    if num_slots > 1 {
+                        bucket.claim_span(num_slots)
+                    } else {
+                        bucket.claim_first_available_slot(#claim_args)
+                    }
+                },
+            )
+        } else {
+            (
+                quote! { #slot_size },
+                quote! { bucket.claim_first_available_slot(#claim_args) },
+            )
+        };
+        let (fits, claim) = if opts.aligned_slot_search {
+            (
+                quote! { size <= #max_size },
+                quote! {
+                    if align <= #align {
+                        #claim
+                    } else {
+                        bucket.claim_aligned_span(num_slots, align)
+                    }
+                },
+            )
+        } else {
+            (quote! { size <= #max_size && align <= #align }, claim)
+        };
         quote! {
-            {
+            #idx => {
                 let bucket = self.#idx_key.get().as_mut().unwrap();
-                bucket.ensure_init();
-                if size <= #slot_size && align <= #align {
-                    if let Some(ptr) = bucket.claim_first_available_slot() {
+                if #fits {
+                    #[allow(unused_variables)]
+                    let num_slots: usize = #num_slots;
+                    if let Some(ptr) = #claim {
+                        #record_alloc
                         return ptr as *mut u8;
                     }
+                    #record_fall_through
+                    #stop_selection
                 }
             }
         }
     }
 
-    fn as_dealloc_bucket_selectors(&self, idx: usize) -> TokenStream {
+    fn as_live_slot_walker(&self, idx: usize) -> TokenStream {
+        let BucketDescriptor {
+            name, slot_size, ..
+        } = self;
+        let idx_key = Index::from(idx);
+        let name = name.to_string();
+        quote! {
+            unsafe { &*self.#idx_key.get() }.for_each_live_slot(|ptr| f(#name, ptr, #slot_size));
+        }
+    }
+
+    fn as_reset(&self, idx: usize, extra_fields: &ExtraFields) -> TokenStream {
+        let idx_key = Index::from(idx);
+        let record_reset = match &extra_fields.stats {
+            Some(stats_key) => quote! {
+                (*self.#stats_key.get())[#idx].record_reset();
+            },
+            None => quote! {},
+        };
+        quote! {
+            (*self.#idx_key.get()).reset();
+            #record_reset
+        }
+    }
+
+    /// Parameters that are not literals can only be validated once the compiler has evaluated them.
+    fn as_const_checks(&self) -> TokenStream {
+        let BucketDescriptor {
+            slot_size,
+            align,
+            num_slots,
+            max_span,
+            ..
+        } = self;
+        let mut checks = vec![];
+        if let BucketParam::Expr(expr) = slot_size {
+            checks.push(quote_spanned! {expr.__span()=>
+                ::core::assert!(#slot_size != 0, "SlotSize must not be zero");
+            });
+        }
+        if let BucketParam::Expr(expr) = num_slots {
+            checks.push(quote_spanned! {expr.__span()=>
+                ::core::assert!(#num_slots != 0, "NumSlots must not be zero");
+            });
+        }
+        if let BucketParam::Expr(expr) = max_span {
+            checks.push(quote_spanned! {expr.__span()=>
+                ::core::assert!(#max_span != 0, "MaxSpan must not be zero");
+            });
+        }
+        if let BucketParam::Expr(expr) = slot_size {
+            checks.push(quote_spanned! {expr.__span()=>
+                ::core::assert!(#slot_size % #align == 0, "SlotSize must be a multiple of Align");
+            });
+        } else if let BucketParam::Expr(expr) = align {
+            checks.push(quote_spanned! {expr.__span()=>
+                ::core::assert!(#slot_size % #align == 0, "SlotSize must be a multiple of Align");
+            });
+        }
+        if checks.is_empty() {
+            return quote! {};
+        }
+        quote! {
+            const _: () = {
+                #(#checks)*
+            };
+        }
+    }
+
+    fn as_dealloc_bucket_selectors(&self, idx: usize, extra_fields: &ExtraFields) -> TokenStream {
         let idx_key = Index::from(idx);
+        let record_free = match &extra_fields.stats {
+            Some(_) => quote! { stats[#idx].record_free(size, num_slots); },
+            None => quote! {},
+        };
+        let num_slots = self.num_slots_for_size();
+        let unset = if self.has_spans() {
+            quote! { bucket.unset_span(slot_idx, num_slots); }
+        } else {
+            quote! { bucket.unset_slot(slot_idx); }
+        };
         quote! {
             {
                 let bucket = self.#idx_key.get().as_mut().unwrap();
-                bucket.ensure_init();
                 if let Some(slot_idx) = bucket.slot_idx_for_ptr(ptr) {
-                    bucket.unset_slot(slot_idx);
+                    // The number of slots an allocation spans is recovered from its layout.
+                    #[allow(unused_variables)]
+                    let num_slots: usize = #num_slots;
+                    #unset
+                    #record_free
                 }
             }
         }
     }
 }
 
+/// Proc macros can’t emit warnings on stable Rust, so this makes the compiler emit one by using a deprecated item.
+fn warning(span: Span, message: &str) -> TokenStream {
+    quote_spanned! {span=>
+        const _: () = {
+            #[deprecated(note = #message)]
+            const BUCKET_ALLOCATOR_WARNING: () = ();
+            BUCKET_ALLOCATOR_WARNING
+        };
+    }
+}
+
+/// Finds buckets that are checked after a bucket that fits every allocation of theirs. These only ever get used once the earlier bucket is full, or never if allocations can’t spill.
+fn shadowed_bucket_warnings(
+    buckets: &[BucketDescriptor],
+    opts: &BucketAllocatorOptions,
+) -> Vec<TokenStream> {
+    let Some(order) = opts.selection.order(buckets) else {
+        return vec![];
+    };
+    let ordered: Vec<&BucketDescriptor> = order.iter().map(|&idx| &buckets[idx]).collect();
+    ordered
+        .iter()
+        .enumerate()
+        .filter_map(|(pos, bucket)| {
+            let (size, align) = bucket.max_size_and_align()?;
+            let shadowing_bucket = ordered[..pos].iter().find(|earlier| {
+                earlier
+                    .max_size_and_align()
+                    .map(|(earlier_size, earlier_align)| {
+                        earlier_size >= size && earlier_align >= align
+                    })
+                    .unwrap_or(false)
+            })?;
+            let usage = if opts.spill {
+                format!("is only used once `{}` is full", shadowing_bucket.name)
+            } else {
+                "is never used".to_string()
+            };
+            Some(warning(
+                bucket.name.span(),
+                &format!(
+                    "bucket `{}` {}, because `{}` is checked first and fits all of its allocations",
+                    bucket.name, usage, shadowing_bucket.name
+                ),
+            ))
+        })
+        .collect()
+}
+
+/// Mirrors `silly_alloc::bucket::Selection`.
+#[derive(Clone, Copy)]
+enum Selection {
+    FirstFit,
+    BestFit,
+    LeastWaste,
+}
+
+impl Parse for Selection {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let name = Ident::parse(input)?;
+        match name.to_string().as_str() {
+            "first_fit" => Ok(Selection::FirstFit),
+            "best_fit" => Ok(Selection::BestFit),
+            "least_waste" => Ok(Selection::LeastWaste),
+            _ => Err(Error::new(
+                name.span(),
+                format!("Unknown selection strategy: {}", name),
+            )),
+        }
+    }
+}
+
+impl ToTokens for Selection {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let variant = match self {
+            Selection::FirstFit => "FirstFit",
+            Selection::BestFit => "BestFit",
+            Selection::LeastWaste => "LeastWaste",
+        };
+        Ident::new(variant, Span::call_site()).to_tokens(tokens)
+    }
+}
+
+impl Selection {
+    /// The order in which the buckets are checked, like `silly_alloc::bucket::selection_order` computes it. Only known to the macro if the order doesn’t depend on the value of a const expression.
+    fn order(&self, buckets: &[BucketDescriptor]) -> Option<Vec<usize>> {
+        let mut order: Vec<usize> = (0..buckets.len()).collect();
+        match self {
+            Selection::FirstFit => {}
+            Selection::BestFit => {
+                let keys = buckets
+                    .iter()
+                    .map(|bucket| bucket.slot_size.as_literal())
+                    .collect::<Option<Vec<_>>>()?;
+                order.sort_by_key(|&idx| keys[idx]);
+            }
+            Selection::LeastWaste => {
+                let keys = buckets
+                    .iter()
+                    .map(|bucket| bucket.sort_key())
+                    .collect::<Option<Vec<_>>>()?;
+                order.sort_by_key(|&idx| keys[idx]);
+            }
+        }
+        Some(order)
+    }
+}
+
+/// Positions of the fields that the generated struct holds in addition to the buckets.
 #[derive(Default)]
+struct ExtraFields {
+    arena: Option<Index>,
+    stats: Option<Index>,
+}
+
 struct BucketAllocatorOptions {
+    crate_path: Path,
     sort_buckets: bool,
+    arena: Option<Type>,
+    stats: bool,
+    size_classes: Option<SizeClasses>,
+    bytes_per_class: Option<usize>,
+    selection: Selection,
+    spill: bool,
+    aligned_slot_search: bool,
 }
 
 impl Parse for BucketAllocatorOptions {
     fn parse(input: ParseStream) -> Result<Self> {
-        let mut result = Self::default();
+        let mut result = Self {
+            crate_path: default_crate_path(),
+            sort_buckets: false,
+            arena: None,
+            stats: false,
+            size_classes: None,
+            bytes_per_class: None,
+            selection: Selection::FirstFit,
+            spill: true,
+            aligned_slot_search: false,
+        };
         while !input.is_empty() {
+            if input.peek(Token![crate]) {
+                <Token![crate]>::parse(input)?;
+                <Token![=]>::parse(input)?;
+                result.crate_path = Path::parse_mod_style(input)?;
+                if !input.is_empty() {
+                    <Token![,]>::parse(input)?;
+                }
+                continue;
+            }
             let opt_name = Ident::parse(input)?.to_string();
             match opt_name.as_str() {
                 "sort_buckets" => {
                     <Token![=]>::parse(input)?;
                     result.sort_buckets = LitBool::parse(input)?.value;
                 }
+                "arena" => {
+                    <Token![=]>::parse(input)?;
+                    result.arena = Some(Type::parse(input)?);
+                }
+                "stats" => {
+                    <Token![=]>::parse(input)?;
+                    result.stats = LitBool::parse(input)?.value;
+                }
+                "size_classes" => {
+                    <Token![=]>::parse(input)?;
+                    result.size_classes = Some(SizeClasses::parse(input)?);
+                }
+                "bytes_per_class" => {
+                    <Token![=]>::parse(input)?;
+                    result.bytes_per_class = Some(LitInt::parse(input)?.base10_parse()?);
+                }
+                "selection" => {
+                    <Token![=]>::parse(input)?;
+                    result.selection = Selection::parse(input)?;
+                }
+                "spill" => {
+                    <Token![=]>::parse(input)?;
+                    result.spill = LitBool::parse(input)?.value;
+                }
+                "aligned_slot_search" => {
+                    <Token![=]>::parse(input)?;
+                    result.aligned_slot_search = LitBool::parse(input)?.value;
+                }
                 _ => return Err(Error::new(input.span(), "Unsupported options")),
             }
+            if !input.is_empty() {
+                <Token![,]>::parse(input)?;
+            }
+        }
+        if let Some(size_classes) = &result.size_classes {
+            if result.bytes_per_class.is_none() {
+                return Err(Error::new(
+                    size_classes.span(),
+                    "size_classes requires bytes_per_class to be specified",
+                ));
+            }
         }
         Ok(result)
     }
 }
 
+/// Whether `attrs` contain a `#[derive(Debug)]`, in which case the macro must not derive `Debug` again.
+fn derives_debug(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("derive"))
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(Meta::List(list)) => Some(list.nested),
+            _ => None,
+        })
+        .flatten()
+        .any(|nested| match nested {
+            NestedMeta::Meta(Meta::Path(path)) => path
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "Debug"),
+            _ => false,
+        })
+}
+
 /// Macro to turn a struct into an allocator.
 ///
 /// `bucket_allocator` is an attribute macro that builds a `GlobalAlloc`-compatible data type from a given struct. Please see the module-level documentation for details and examples.
 ///
-/// The macro supports the following options:
+/// The macro supports the following options. Multiple options are separated by commas, e.g. `#[bucket_allocator(sort_buckets = true, stats = true)]`:
+/// - `crate = <path>`: Path to the `silly_alloc` crate. Only needed when `silly_alloc` is not a direct dependency, e.g. because the macro is re-exported by another crate. Renamed dependencies are detected automatically.
 /// - `sort_buckets = <true|false>`: Sort buckets by item size, then alignment
+/// - `arena = <type>`: Carve the buckets’ segments from an arena of the given [`BumpAllocatorArena`](../silly_alloc/bump/trait.BumpAllocatorArena.html) type as they fill up. The allocator is then created with `with_arena()` instead of `new()`.
+/// - `stats = <true|false>`: Track usage statistics for each bucket, which can be retrieved with the generated `stats()` method.
+/// - `size_classes = geometric(min = <n>, max = <n>, ratio = <f>)`: Generate buckets with slot sizes from `min` to `max`, where each size is `ratio` times the previous one, rounded up to a multiple of 8. Generated buckets are added after the buckets declared in the struct.
+/// - `size_classes = powers_of_two(min = <n>, max = <n>)`: Generate buckets for all powers of two from `min` to `max`.
+/// - `bytes_per_class = <n>`: Number of bytes each generated bucket holds. Required with `size_classes`.
+/// - `selection = <first_fit|best_fit|least_waste>`: Order in which buckets are checked for an allocation. Defaults to `first_fit`, which is the order of declaration.
+/// - `spill = <true|false>`: Whether an allocation may spill into the next bucket that fits if a bucket is full. Defaults to `true`.
+/// - `aligned_slot_search = <true|false>`: Let buckets serve allocations with a bigger alignment than their own by looking for a free slot that happens to be sufficiently aligned.
 #[proc_macro_attribute]
 pub fn bucket_allocator(
     attr: proc_macro::TokenStream,
     input: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
-    let BucketAllocatorOptions { sort_buckets, .. } = parse_macro_input!(attr);
-    let BucketAllocatorDescriptor { name, mut buckets } = parse_macro_input!(input);
+    let opts: BucketAllocatorOptions = parse_macro_input!(attr);
+    let BucketAllocatorDescriptor {
+        attrs,
+        vis,
+        name,
+        mut buckets,
+    } = parse_macro_input!(input);
 
-    let crate_path = crate_path();
+    let crate_path = &opts.crate_path;
 
-    if sort_buckets {
-        buckets.sort_by(|a, b| {
-            let cmp = a.slot_size.cmp(&b.slot_size);
-            if cmp == std::cmp::Ordering::Equal {
-                a.align.cmp(&b.align)
-            } else {
-                cmp
-            }
-        });
+    if let (Some(size_classes), Some(bytes_per_class)) = (&opts.size_classes, opts.bytes_per_class)
+    {
+        buckets.extend(size_classes.to_buckets(bytes_per_class));
     }
 
+    if opts.arena.is_some() {
+        if let Some(bucket) = buckets.iter().find(|bucket| bucket.has_spans()) {
+            return Error::new(
+                bucket.name.span(),
+                "MaxSpan is not supported in combination with the arena option",
+            )
+            .to_compile_error()
+            .into();
+        }
+        if let Some(bucket) = buckets.iter().find(|bucket| bucket.has_quarantine()) {
+            return Error::new(
+                bucket.name.span(),
+                "Quarantine is not supported in combination with the arena option",
+            )
+            .to_compile_error()
+            .into();
+        }
+        if opts.aligned_slot_search {
+            return Error::new(
+                Span::call_site(),
+                "aligned_slot_search is not supported in combination with the arena option",
+            )
+            .to_compile_error()
+            .into();
+        }
+    }
+
+    if opts.sort_buckets {
+        if let Some(bucket) = buckets.iter().find(|bucket| bucket.sort_key().is_none()) {
+            return Error::new(
+                bucket.name.span(),
+                "sort_buckets requires SlotSize and Align to be integer literals",
+            )
+            .to_compile_error()
+            .into();
+        }
+        buckets.sort_by_key(|bucket| bucket.sort_key());
+    }
+
+    let shadowed_bucket_warnings = shadowed_bucket_warnings(&buckets, &opts);
+
+    let const_checks: Vec<TokenStream> = buckets
+        .iter()
+        .map(|bucket| bucket.as_const_checks())
+        .collect();
+
     let bucket_field_decls: Vec<TokenStream> = buckets
         .iter()
-        .map(|bucket| bucket.as_struct_fields())
+        .map(|bucket| bucket.as_struct_fields(&opts))
         .collect();
 
     let bucket_field_inits: Vec<TokenStream> = buckets
         .iter()
-        .map(|bucket| bucket.as_init_values())
+        .map(|bucket| bucket.as_init_values(&opts))
         .collect();
 
+    let derive_debug = if derives_debug(&attrs) {
+        quote! {}
+    } else {
+        quote! { #[derive(Debug)] }
+    };
+
+    let bucket_accessors: Vec<TokenStream> = buckets
+        .iter()
+        .enumerate()
+        .map(|(idx, bucket)| bucket.as_accessor(idx, &vis, &opts))
+        .collect();
+
+    let num_buckets = buckets.len();
+
+    // Non-bucket fields are stored after the last bucket.
+    let mut extra_field_decls: Vec<TokenStream> = vec![];
+    let mut extra_field_inits: Vec<TokenStream> = vec![];
+    let mut extra_fields = ExtraFields::default();
+
+    // Hygienic, so it can’t clash with any of the caller’s items.
+    let arena = Ident::new("arena", Span::mixed_site());
+    if let Some(arena_type) = &opts.arena {
+        extra_fields.arena = Some(Index::from(buckets.len() + extra_field_decls.len()));
+        extra_field_decls.push(quote! {
+            #crate_path::bump::BumpAllocator<'static, #arena_type, #crate_path::bump::SingleThreadedHead>
+        });
+        extra_field_inits.push(quote! {
+            #crate_path::bump::BumpAllocator::new(#arena, #crate_path::bump::SingleThreadedHead::new())
+        });
+    }
+
+    if opts.stats {
+        extra_fields.stats = Some(Index::from(buckets.len() + extra_field_decls.len()));
+        let stats_inits: Vec<TokenStream> = buckets
+            .iter()
+            .map(|bucket| bucket.as_stats_init_value(&opts))
+            .collect();
+        extra_field_decls.push(quote! {
+            ::core::cell::UnsafeCell<[#crate_path::bucket::BucketStats; #num_buckets]>
+        });
+        extra_field_inits.push(quote! {
+            ::core::cell::UnsafeCell::new([#(#stats_inits),*])
+        });
+    }
+
     let alloc_bucket_selectors: Vec<TokenStream> = buckets
         .iter()
         .enumerate()
-        .map(|(idx, bucket)| bucket.as_alloc_bucket_selectors(idx))
+        .map(|(idx, bucket)| bucket.as_alloc_bucket_selectors(idx, &extra_fields, &opts))
         .collect();
 
+    let bucket_params: Vec<TokenStream> = buckets
+        .iter()
+        .map(|bucket| {
+            let BucketDescriptor {
+                slot_size, align, ..
+            } = bucket;
+            quote! { (#slot_size, #align) }
+        })
+        .collect();
+    let selection = opts.selection;
+
     let dealloc_bucket_selectors: Vec<TokenStream> = buckets
         .iter()
         .enumerate()
-        .map(|(idx, bucket)| bucket.as_dealloc_bucket_selectors(idx))
+        .map(|(idx, bucket)| bucket.as_dealloc_bucket_selectors(idx, &extra_fields))
         .collect();
 
+    let constructor = match &opts.arena {
+        Some(arena_type) => quote! {
+            /// Creates an allocator with empty buckets that grow into `arena`.
+            #vis const fn with_arena(#arena: #arena_type) -> Self {
+                #name (
+                    #(#bucket_field_inits,)*
+                    #(#extra_field_inits,)*
+                )
+            }
+        },
+        None => quote! {
+            /// Creates an allocator with empty buckets.
+            #vis const fn new() -> Self {
+                #name (
+                    #(#bucket_field_inits,)*
+                    #(#extra_field_inits,)*
+                )
+            }
+        },
+    };
+
+    // The stats hold the buckets’ names as `&'static str`, which must not be null.
+    let zeroable = match &extra_fields.stats {
+        Some(_) => quote! {},
+        None => quote! {
+            unsafe impl #crate_path::bucket::Zeroable for #name {}
+        },
+    };
+    let default_and_zeroable = match &opts.arena {
+        Some(_) => quote! {},
+        None => quote! {
+            impl ::core::default::Default for #name {
+                fn default() -> Self {
+                    Self::new()
+                }
+            }
+
+            #zeroable
+        },
+    };
+
+    let bucket_resets: Vec<TokenStream> = buckets
+        .iter()
+        .enumerate()
+        .map(|(idx, bucket)| bucket.as_reset(idx, &extra_fields))
+        .collect();
+    let live_slot_walkers: Vec<TokenStream> = buckets
+        .iter()
+        .enumerate()
+        .map(|(idx, bucket)| bucket.as_live_slot_walker(idx))
+        .collect();
+    let bucket_names: Vec<String> = buckets
+        .iter()
+        .map(|bucket| bucket.name.to_string())
+        .collect();
+
+    let (stats_method, alloc_prologue, alloc_epilogue, dealloc_prologue) = match &extra_fields.stats
+    {
+        Some(stats_key) => (
+            quote! {
+                /// Returns a snapshot of the usage statistics of each bucket.
+                #[allow(dead_code)]
+                #vis fn stats(&self) -> [#crate_path::bucket::BucketStats; #num_buckets] {
+                    unsafe { *self.#stats_key.get() }
+                }
+            },
+            quote! {
+                let stats = self.#stats_key.get().as_mut().unwrap();
+                // The first bucket that could have served the request is blamed for failures.
+                let mut first_candidate: ::core::option::Option<usize> = None;
+            },
+            quote! {
+                if let Some(idx) = first_candidate {
+                    stats[idx].record_failure();
+                }
+            },
+            quote! {
+                let stats = self.#stats_key.get().as_mut().unwrap();
+            },
+        ),
+        None => (quote! {}, quote! {}, quote! {}, quote! {}),
+    };
+
     quote! {
-            #[derive(Default, Debug)]
-            struct #name(
-                #(#bucket_field_decls),*
+            #(#attrs)*
+            #derive_debug
+            #vis struct #name(
+                #(#bucket_field_decls,)*
+                #(#extra_field_decls,)*
             );
 
             impl #name {
-                const fn new() -> Self {
-                    #name (
-                        #(#bucket_field_inits),*
-                    )
+                #constructor
+
+                #(#bucket_accessors)*
+
+                #stats_method
+
+                /// Calls `f` with the bucket name, address and slot size of every slot that is in use. An allocation spanning multiple slots is reported as its individual slots.
+                #[allow(dead_code)]
+                #vis fn for_each_live(&self, mut f: impl FnMut(&'static str, *const u8, usize)) {
+                    #(#live_slot_walkers)*
+                }
+
+                /// Frees all allocations in all buckets at once.
+                ///
+                /// # Safety
+                /// It’s the callers responsibility to ensure that there are no live values in any of the buckets. Any leaked values will not have their `Drop` function called.
+                #[allow(dead_code)]
+                #vis unsafe fn reset(&self) {
+                    #(#bucket_resets)*
+                }
+
+                /// Frees all allocations in the bucket called `name` at once. Returns `false` if there is no such bucket.
+                ///
+                /// # Safety
+                /// It’s the callers responsibility to ensure that there are no live values in the bucket. Any leaked values will not have their `Drop` function called.
+                #[allow(dead_code)]
+                #vis unsafe fn reset_bucket(&self, name: &str) -> bool {
+                    match name {
+                        #(#bucket_names => {
+                            #bucket_resets
+                            true
+                        })*
+                        _ => false,
+                    }
                 }
             }
 
-            unsafe impl ::core::marker::Sync for #name {}
+            #default_and_zeroable
 
-            unsafe impl #crate_path::bucket::Zeroable for #name {}
+            #(#const_checks)*
+
+            #(#shadowed_bucket_warnings)*
+
+            unsafe impl ::core::marker::Sync for #name {}
 
             unsafe impl ::core::alloc::GlobalAlloc for #name {
                 unsafe fn alloc(&self, layout: ::core::alloc::Layout) -> *mut u8 {
                     let size = layout.size();
                     let align = layout.align();
-                    #(#alloc_bucket_selectors)*
+                    #alloc_prologue
+                    const ORDER: [usize; #num_buckets] = #crate_path::bucket::selection_order(
+                        [#(#bucket_params),*],
+                        #crate_path::bucket::Selection::#selection,
+                    );
+                    for idx in ORDER {
+                        match idx {
+                            #(#alloc_bucket_selectors)*
+                            _ => {}
+                        }
+                    }
+                    #alloc_epilogue
                     core::ptr::null_mut()
                 }
 
                 unsafe fn dealloc(&self, ptr: *mut u8, layout: ::core::alloc::Layout) {
                     let size = layout.size();
                     let align = layout.align();
+                    #dealloc_prologue
                     #(#dealloc_bucket_selectors)*
                 }
 
@@ -0,0 +1,143 @@
+use proc_macro2::Span;
+use syn::{
+    parenthesized,
+    parse::{Parse, ParseStream},
+    Error, Ident, Lit, Result, Token,
+};
+
+use crate::{BucketDescriptor, BucketParam};
+
+/// Generated size classes never get an alignment bigger than this.
+const MAX_ALIGN: usize = 512;
+
+/// A rule to generate a series of bucket sizes from, as given by the `size_classes` option.
+pub enum SizeClasses {
+    /// Each size is `ratio` times the previous size, starting at `min` up to `max`.
+    Geometric {
+        min: usize,
+        max: usize,
+        ratio: f64,
+        span: Span,
+    },
+    /// All powers of two from `min` up to `max`.
+    PowersOfTwo { min: usize, max: usize, span: Span },
+}
+
+impl Parse for SizeClasses {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let kind = Ident::parse(input)?;
+        let span = kind.span();
+        let content;
+        parenthesized!(content in input);
+
+        let mut min: Option<usize> = None;
+        let mut max: Option<usize> = None;
+        let mut ratio: Option<f64> = None;
+        while !content.is_empty() {
+            let param_name = Ident::parse(&content)?;
+            <Token![=]>::parse(&content)?;
+            let value = Lit::parse(&content)?;
+            match (param_name.to_string().as_str(), &value) {
+                ("min", Lit::Int(v)) => min = Some(v.base10_parse()?),
+                ("max", Lit::Int(v)) => max = Some(v.base10_parse()?),
+                ("ratio", Lit::Float(v)) => ratio = Some(v.base10_parse()?),
+                ("ratio", Lit::Int(v)) => ratio = Some(v.base10_parse()?),
+                ("min" | "max" | "ratio", _) => {
+                    return Err(Error::new(value.span(), "Expected a number"))
+                }
+                _ => {
+                    return Err(Error::new(
+                        param_name.span(),
+                        format!("Unknown size class parameter: {}", param_name),
+                    ))
+                }
+            }
+            if !content.is_empty() {
+                <Token![,]>::parse(&content)?;
+            }
+        }
+
+        let min = min.ok_or_else(|| Error::new(span, "min was not specified"))?;
+        let max = max.ok_or_else(|| Error::new(span, "max was not specified"))?;
+        if min == 0 || min > max {
+            return Err(Error::new(span, "min must be non-zero and at most max"));
+        }
+        match kind.to_string().as_str() {
+            "geometric" => {
+                let ratio = ratio.ok_or_else(|| Error::new(span, "ratio was not specified"))?;
+                if ratio <= 1.0 {
+                    return Err(Error::new(span, "ratio must be bigger than 1"));
+                }
+                Ok(SizeClasses::Geometric {
+                    min,
+                    max,
+                    ratio,
+                    span,
+                })
+            }
+            "powers_of_two" => {
+                if ratio.is_some() {
+                    return Err(Error::new(span, "powers_of_two does not take a ratio"));
+                }
+                Ok(SizeClasses::PowersOfTwo { min, max, span })
+            }
+            _ => Err(Error::new(
+                span,
+                format!("Unknown size class kind: {}", kind),
+            )),
+        }
+    }
+}
+
+impl SizeClasses {
+    pub fn span(&self) -> Span {
+        match self {
+            SizeClasses::Geometric { span, .. } | SizeClasses::PowersOfTwo { span, .. } => *span,
+        }
+    }
+
+    /// Returns the slot sizes of all size classes in ascending order.
+    pub fn sizes(&self) -> Vec<usize> {
+        let mut sizes = vec![];
+        match *self {
+            SizeClasses::Geometric {
+                min, max, ratio, ..
+            } => {
+                // Sizes are rounded to a multiple of the word size (or of `min`, if it’s smaller), so that they get reasonable alignments.
+                let quantum = min.next_power_of_two().min(8);
+                let mut size = min;
+                while size < max {
+                    sizes.push(size);
+                    let next = ((size as f64) * ratio).ceil() as usize;
+                    size = next.next_multiple_of(quantum).max(size + quantum);
+                }
+                sizes.push(max);
+            }
+            SizeClasses::PowersOfTwo { min, max, .. } => {
+                let mut size = min.next_power_of_two();
+                while size <= max {
+                    sizes.push(size);
+                    size *= 2;
+                }
+            }
+        }
+        sizes
+    }
+
+    /// Turns the size classes into bucket descriptors, where each bucket takes up roughly `bytes_per_class` bytes.
+    pub fn to_buckets(&self, bytes_per_class: usize) -> Vec<BucketDescriptor> {
+        self.sizes()
+            .into_iter()
+            .map(|slot_size| BucketDescriptor {
+                name: Ident::new(&format!("size_class_{}", slot_size), self.span()),
+                docs: vec![],
+                slot_size: BucketParam::Literal(slot_size),
+                // The biggest power of two that divides the size.
+                align: BucketParam::Literal((1 << slot_size.trailing_zeros()).min(MAX_ALIGN)),
+                num_slots: BucketParam::Literal((bytes_per_class / slot_size).max(1)),
+                max_span: BucketParam::Literal(1),
+                quarantine: BucketParam::Literal(0),
+            })
+            .collect()
+    }
+}
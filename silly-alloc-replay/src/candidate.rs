@@ -0,0 +1,16 @@
+//! The bucket allocator configuration that `--allocator bucket` replays against.
+//!
+//! Replace the buckets with the layout to evaluate, e.g. one generated by `RecordingAllocator::write_bucket_allocator`, and rebuild. `stats = true` has to stay, as the per-bucket report relies on it.
+
+use silly_alloc::bucket_allocator;
+
+#[bucket_allocator(stats = true, selection = best_fit)]
+pub struct Candidate {
+    class_8: Bucket<SlotSize<8>, NumSlots<4096>, Align<8>>,
+    class_16: Bucket<SlotSize<16>, NumSlots<4096>, Align<16>>,
+    class_32: Bucket<SlotSize<32>, NumSlots<2048>, Align<16>>,
+    class_64: Bucket<SlotSize<64>, NumSlots<1024>, Align<16>>,
+    class_256: Bucket<SlotSize<256>, NumSlots<512>, Align<16>>,
+    class_1024: Bucket<SlotSize<1024>, NumSlots<128>, Align<16>>,
+    class_4096: Bucket<SlotSize<4096>, NumSlots<64>, Align<4096>>,
+}
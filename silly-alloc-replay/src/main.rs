@@ -0,0 +1,486 @@
+/*!
+`silly-alloc-replay` replays an allocation trace, as drained from a [`TracingAllocator`](silly_alloc::trace::TracingAllocator), against one or more allocators and reports how they coped with it.
+
+```shell
+$ silly-alloc-replay --allocator bucket --allocator system trace.bin
+```
+
+Available allocators are `system`, `bump` (a `SliceBumpAllocator` over `--arena-size` bytes, 64 MiB by default) and `bucket`, the bucket allocator defined in `src/candidate.rs`. To evaluate a different bucket layout, edit that file and rebuild. Without `--allocator`, the trace is replayed against all of them.
+
+For every allocator, the report contains:
+
+- The number of allocations that failed.
+- The peak number of bytes that were requested and live at the same time.
+- The peak footprint. For the system and bump allocators, this is the size of the address range spanned by the live allocations. For the system allocator, this is usually not meaningful, as big allocations are placed far away from the others. For the bucket allocator, it is the size of the slots occupied by the live allocations, as tracked by the bucket stats, and the size of the allocator itself is reported as well.
+- The fragmentation, i.e. the share of the peak footprint that goes beyond the peak live bytes. For the bucket allocator, this is the internal fragmentation, i.e. the bytes wasted in slots that are bigger than the allocations they hold.
+- The average time per operation.
+
+Deallocations of pointers that are not known to be live, e.g. because events were lost from the ring buffer, are skipped and counted. If a reallocation fails, the allocation keeps its old size, and the events after it refer to it by its new pointer from the trace.
+*/
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+
+use silly_alloc::{
+    trace::{self, Event, EventKind},
+    SliceBumpAllocator,
+};
+
+mod candidate;
+use candidate::Candidate;
+
+const DEFAULT_ARENA_SIZE: usize = 64 * 1024 * 1024;
+
+const USAGE: &str =
+    "Usage: silly-alloc-replay [--allocator system|bump|bucket]... [--arena-size BYTES] <TRACE>";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AllocatorChoice {
+    System,
+    Bump,
+    Bucket,
+}
+
+impl AllocatorChoice {
+    const ALL: [AllocatorChoice; 3] = [
+        AllocatorChoice::System,
+        AllocatorChoice::Bump,
+        AllocatorChoice::Bucket,
+    ];
+
+    fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "system" => Ok(AllocatorChoice::System),
+            "bump" => Ok(AllocatorChoice::Bump),
+            "bucket" => Ok(AllocatorChoice::Bucket),
+            _ => Err(format!("Unknown allocator: {}", name)),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            AllocatorChoice::System => "system",
+            AllocatorChoice::Bump => "bump",
+            AllocatorChoice::Bucket => "bucket",
+        }
+    }
+}
+
+struct Options {
+    allocators: Vec<AllocatorChoice>,
+    arena_size: usize,
+    trace_path: String,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut allocators = vec![];
+        let mut arena_size = DEFAULT_ARENA_SIZE;
+        let mut trace_path = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--allocator" => {
+                    let name = args.next().ok_or("--allocator needs a value")?;
+                    allocators.push(AllocatorChoice::parse(&name)?);
+                }
+                "--arena-size" => {
+                    let size = args.next().ok_or("--arena-size needs a value")?;
+                    arena_size = size
+                        .parse()
+                        .map_err(|err| format!("Invalid arena size {}: {}", size, err))?;
+                }
+                _ if trace_path.is_none() && !arg.starts_with("--") => trace_path = Some(arg),
+                _ => return Err(format!("Unexpected argument: {}", arg)),
+            }
+        }
+        if allocators.is_empty() {
+            allocators.extend(AllocatorChoice::ALL);
+        }
+        Ok(Options {
+            allocators,
+            arena_size,
+            trace_path: trace_path.ok_or("No trace given")?,
+        })
+    }
+}
+
+/// How the footprint of the live allocations is measured.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Footprint {
+    /// The size of the address range spanned by the live allocations.
+    #[default]
+    AddressRange,
+    /// The size of the bucket slots occupied by the live allocations.
+    Slots,
+}
+
+#[derive(Debug, Default)]
+struct Report {
+    allocs: usize,
+    deallocs: usize,
+    reallocs: usize,
+    failed_allocs: usize,
+    /// Events that couldn’t be replayed, e.g. deallocations of pointers that are not live.
+    skipped: usize,
+    peak_live_bytes: usize,
+    footprint: Footprint,
+    peak_footprint: usize,
+    alloc_time: Duration,
+    dealloc_time: Duration,
+    realloc_time: Duration,
+}
+
+fn per_op(time: Duration, ops: usize) -> f64 {
+    if ops == 0 {
+        return 0.0;
+    }
+    time.as_nanos() as f64 / ops as f64
+}
+
+impl Report {
+    fn fragmentation(&self) -> f64 {
+        if self.peak_footprint == 0 {
+            return 0.0;
+        }
+        1.0 - self.peak_live_bytes as f64 / self.peak_footprint as f64
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "  operations:          {} allocs, {} deallocs, {} reallocs",
+            self.allocs, self.deallocs, self.reallocs
+        )?;
+        writeln!(f, "  failed allocations:  {}", self.failed_allocs)?;
+        writeln!(f, "  skipped events:      {}", self.skipped)?;
+        writeln!(f, "  peak live bytes:     {}", self.peak_live_bytes)?;
+        let (footprint, fragmentation) = match self.footprint {
+            Footprint::AddressRange => ("address range", "external"),
+            Footprint::Slots => ("occupied slots", "internal"),
+        };
+        writeln!(
+            f,
+            "  peak footprint:      {} ({})",
+            self.peak_footprint, footprint
+        )?;
+        writeln!(
+            f,
+            "  fragmentation:       {:.1}% ({})",
+            self.fragmentation() * 100.0,
+            fragmentation
+        )?;
+        writeln!(
+            f,
+            "  time per operation:  alloc {:.0} ns, dealloc {:.0} ns, realloc {:.0} ns",
+            per_op(self.alloc_time, self.allocs),
+            per_op(self.dealloc_time, self.deallocs),
+            per_op(self.realloc_time, self.reallocs),
+        )
+    }
+}
+
+/// Keeps track of the replayed allocations that are live.
+#[derive(Default)]
+struct LiveSet {
+    /// Replayed pointer and layout of each traced pointer.
+    by_traced_ptr: HashMap<u64, (*mut u8, Layout)>,
+    /// Start and end address of each replayed allocation.
+    ranges: BTreeMap<usize, usize>,
+    live_bytes: usize,
+}
+
+impl LiveSet {
+    fn insert(&mut self, traced_ptr: u64, ptr: *mut u8, layout: Layout) {
+        self.by_traced_ptr.insert(traced_ptr, (ptr, layout));
+        self.ranges
+            .insert(ptr as usize, ptr as usize + layout.size());
+        self.live_bytes += layout.size();
+    }
+
+    fn remove(&mut self, traced_ptr: u64) -> Option<(*mut u8, Layout)> {
+        let (ptr, layout) = self.by_traced_ptr.remove(&traced_ptr)?;
+        self.ranges.remove(&(ptr as usize));
+        self.live_bytes -= layout.size();
+        Some((ptr, layout))
+    }
+
+    /// Size of the address range spanned by all live allocations.
+    fn footprint(&self) -> usize {
+        // Allocations don’t overlap, so the one that starts last also ends last.
+        match (self.ranges.first_key_value(), self.ranges.last_key_value()) {
+            (Some((&start, _)), Some((_, &end))) => end - start,
+            _ => 0,
+        }
+    }
+}
+
+fn layout_of(event: &Event, size: u64) -> Option<Layout> {
+    // `GlobalAlloc` doesn’t allow zero-sized allocations.
+    if size == 0 {
+        return None;
+    }
+    Layout::from_size_align(size.try_into().ok()?, event.align.try_into().ok()?).ok()
+}
+
+/// Replays `events` against `allocator`. All allocations that are still live at the end are freed.
+///
+/// With `wasted_bytes`, which returns the number of bytes wasted in occupied slots, the footprint is measured in slots rather than addresses.
+fn replay(
+    events: &[Event],
+    allocator: &dyn GlobalAlloc,
+    wasted_bytes: Option<&dyn Fn() -> usize>,
+) -> Report {
+    let mut report = Report {
+        footprint: match wasted_bytes {
+            Some(_) => Footprint::Slots,
+            None => Footprint::AddressRange,
+        },
+        ..Report::default()
+    };
+    let mut live = LiveSet::default();
+    let mut pending_realloc: Option<u64> = None;
+    for event in events {
+        match event.kind {
+            EventKind::Alloc => {
+                let Some(layout) = layout_of(event, event.size) else {
+                    report.skipped += 1;
+                    continue;
+                };
+                report.allocs += 1;
+                let start = Instant::now();
+                let ptr = unsafe { allocator.alloc(layout) };
+                report.alloc_time += start.elapsed();
+                if ptr.is_null() {
+                    report.failed_allocs += 1;
+                    continue;
+                }
+                // Failed allocations in the trace are replayed, but there is nothing to free later.
+                if event.ptr == 0 {
+                    unsafe { allocator.dealloc(ptr, layout) };
+                    continue;
+                }
+                live.insert(event.ptr, ptr, layout);
+            }
+            EventKind::Dealloc => {
+                let Some((ptr, layout)) = live.remove(event.ptr) else {
+                    report.skipped += 1;
+                    continue;
+                };
+                report.deallocs += 1;
+                let start = Instant::now();
+                unsafe { allocator.dealloc(ptr, layout) };
+                report.dealloc_time += start.elapsed();
+            }
+            EventKind::Realloc => {
+                pending_realloc = Some(event.ptr);
+                continue;
+            }
+            EventKind::Grow | EventKind::Shrink => {
+                let Some(traced_ptr) = pending_realloc.take() else {
+                    report.skipped += 1;
+                    continue;
+                };
+                let Some(layout) = layout_of(event, event.size) else {
+                    report.skipped += 1;
+                    continue;
+                };
+                let Some((ptr, old_layout)) = live.remove(traced_ptr) else {
+                    report.skipped += 1;
+                    continue;
+                };
+                report.reallocs += 1;
+                let start = Instant::now();
+                let new_ptr = unsafe { allocator.realloc(ptr, old_layout, layout.size()) };
+                report.realloc_time += start.elapsed();
+                if new_ptr.is_null() {
+                    // The old allocation stays valid. Later events refer to it by the pointer the reallocation returned in the trace.
+                    report.failed_allocs += 1;
+                    live.insert(event.ptr, ptr, old_layout);
+                    continue;
+                }
+                live.insert(event.ptr, new_ptr, layout);
+            }
+        }
+        report.peak_live_bytes = report.peak_live_bytes.max(live.live_bytes);
+        let footprint = match wasted_bytes {
+            Some(wasted_bytes) => live.live_bytes + wasted_bytes(),
+            None => live.footprint(),
+        };
+        report.peak_footprint = report.peak_footprint.max(footprint);
+    }
+    for (ptr, layout) in live.by_traced_ptr.into_values() {
+        unsafe { allocator.dealloc(ptr, layout) };
+    }
+    report
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let trace = std::fs::read(&options.trace_path)
+        .map_err(|err| format!("Could not read {}: {}", options.trace_path, err))?;
+    let events = trace::decode(&trace)
+        .and_then(|events| events.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| format!("Could not decode {}: {}", options.trace_path, err))?;
+    println!("{} events in {}", events.len(), options.trace_path);
+
+    for allocator in &options.allocators {
+        println!();
+        println!("{}:", allocator.name());
+        match allocator {
+            AllocatorChoice::System => print!("{}", replay(&events, &System, None)),
+            AllocatorChoice::Bump => {
+                let arena: &'static [u8] = vec![0u8; options.arena_size].leak();
+                let allocator = SliceBumpAllocator::with_slice(arena);
+                print!("{}", replay(&events, &allocator, None));
+            }
+            AllocatorChoice::Bucket => replay_candidate(&events),
+        }
+    }
+    Ok(())
+}
+
+/// An unused candidate that every run starts from.
+static PRISTINE_CANDIDATE: Candidate = Candidate::new();
+
+/// Creates a fresh [`Candidate`] on the heap, so that neither live slots nor stats carry over from an earlier run.
+fn new_candidate() -> Box<Candidate> {
+    // Candidates are easily too big for the stack, so the pristine one is copied straight to the heap.
+    let layout = Layout::new::<Candidate>();
+    unsafe {
+        let ptr = std::alloc::alloc(layout) as *mut Candidate;
+        if ptr.is_null() {
+            std::alloc::handle_alloc_error(layout);
+        }
+        ptr.copy_from_nonoverlapping(&PRISTINE_CANDIDATE, 1);
+        Box::from_raw(ptr)
+    }
+}
+
+/// Replays `events` against a fresh [`Candidate`] and prints the report including the bucket stats.
+fn replay_candidate(events: &[Event]) {
+    let candidate = new_candidate();
+    let wasted_bytes = || {
+        candidate
+            .stats()
+            .iter()
+            .map(|stats| stats.wasted_bytes)
+            .sum()
+    };
+    print!("{}", replay(events, &*candidate, Some(&wasted_bytes)));
+    println!(
+        "  allocator size:      {}",
+        std::mem::size_of::<Candidate>()
+    );
+    for stats in candidate.stats() {
+        println!(
+            "  {:<20} peak {} of {} slots, {} fall-throughs, {} failures",
+            stats.name, stats.peak_live_slots, stats.capacity, stats.fall_throughs, stats.failures
+        );
+    }
+}
+
+fn main() -> ExitCode {
+    let result = Options::parse(std::env::args().skip(1)).and_then(|options| run(&options));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use silly_alloc::trace::{TracingAllocator, HEADER_SIZE};
+
+    fn record(f: impl FnOnce(&TracingAllocator<System, 64>)) -> Vec<Event> {
+        let allocator = TracingAllocator::new(System);
+        f(&allocator);
+        let mut buf = [0u8; 4096];
+        trace::write_header(&mut buf);
+        let len = HEADER_SIZE + allocator.drain(&mut buf[HEADER_SIZE..]);
+        trace::decode(&buf[..len])
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn replays_traces() {
+        let events = record(|allocator| unsafe {
+            let l16 = Layout::from_size_align(16, 8).unwrap();
+            let l64 = Layout::from_size_align(64, 8).unwrap();
+            let a = allocator.alloc(l16);
+            let b = allocator.alloc(l64);
+            let c = allocator.alloc(l16);
+            allocator.dealloc(b, l64);
+            let c = allocator.realloc(c, l16, 64);
+            allocator.dealloc(c, l64);
+            allocator.dealloc(a, l16);
+        });
+        assert_eq!(events.len(), 8);
+
+        let arena: &'static [u8] = vec![0u8; 1024].leak();
+        let report = replay(&events, &SliceBumpAllocator::with_slice(arena), None);
+        assert_eq!((report.allocs, report.deallocs, report.reallocs), (3, 3, 1));
+        assert_eq!(report.failed_allocs, 0);
+        assert_eq!(report.skipped, 0);
+        assert_eq!(report.peak_live_bytes, 96);
+        // The bump allocator can’t reuse the memory freed by `b`, so the reallocated `c` ends up after it.
+        assert_eq!(report.peak_footprint, 16 + 64 + 16 + 64);
+        assert!((report.fragmentation() - 0.4).abs() < 1e-9);
+
+        let arena: &'static [u8] = vec![0u8; 64].leak();
+        let report = replay(&events, &SliceBumpAllocator::with_slice(arena), None);
+        // `b` and the reallocation of `c` fail. Freeing `b` is skipped, while `c` is freed with its old size.
+        assert_eq!(report.failed_allocs, 2);
+        assert_eq!(report.skipped, 1);
+        assert_eq!((report.allocs, report.deallocs, report.reallocs), (3, 2, 1));
+        assert_eq!(report.peak_live_bytes, 32);
+    }
+
+    #[test]
+    fn internal_fragmentation() {
+        let events = record(|allocator| unsafe {
+            let l10 = Layout::from_size_align(10, 8).unwrap();
+            let l40 = Layout::from_size_align(40, 8).unwrap();
+            let a = allocator.alloc(l10);
+            let b = allocator.alloc(l40);
+            allocator.dealloc(a, l10);
+            allocator.dealloc(b, l40);
+        });
+
+        let candidate = new_candidate();
+        let wasted_bytes = || candidate.stats().iter().map(|s| s.wasted_bytes).sum();
+        let report = replay(&events, &*candidate, Some(&wasted_bytes));
+        assert_eq!(report.footprint, Footprint::Slots);
+        assert_eq!(report.peak_live_bytes, 50);
+        // The allocations end up in the 16- and 64-byte buckets.
+        assert_eq!(report.peak_footprint, 16 + 64);
+        assert!((report.fragmentation() - 30.0 / 80.0).abs() < 1e-9);
+        assert_eq!(wasted_bytes(), 0);
+    }
+
+    #[test]
+    fn options() {
+        let args = ["--allocator", "bump", "--arena-size", "1024", "trace.bin"];
+        let options = Options::parse(args.into_iter().map(String::from)).unwrap();
+        assert_eq!(options.allocators, [AllocatorChoice::Bump]);
+        assert_eq!(options.arena_size, 1024);
+        assert_eq!(options.trace_path, "trace.bin");
+
+        let options = Options::parse(["trace.bin".to_string()].into_iter()).unwrap();
+        assert_eq!(options.allocators, AllocatorChoice::ALL);
+        assert!(Options::parse(["--allocator".to_string()].into_iter()).is_err());
+        assert!(Options::parse(std::iter::empty()).is_err());
+    }
+}
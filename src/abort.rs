@@ -0,0 +1,17 @@
+use core::fmt::Arguments;
+
+/// Panics with `message`, but aborts instead of unwinding.
+///
+/// Allocators must not unwind, so this is how they report fatal errors. If panics unwind, the guard’s `Drop` panics again while unwinding, which aborts.
+#[cold]
+pub(crate) fn abort(message: Arguments<'_>) -> ! {
+    struct Guard;
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            panic!("Aborting, as allocators must not unwind");
+        }
+    }
+
+    let _guard = Guard;
+    panic!("{}", message);
+}
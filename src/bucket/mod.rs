@@ -21,7 +21,7 @@ Note that these types and generics are not really in use. They are merely there
 
 The new bucket allocator can then be instantiated and used as a global allocator as per usual:
 
-```rust
+```rust,no_run
 # use silly_alloc::bucket_allocator;
 #
 # #[bucket_allocator]
@@ -33,7 +33,163 @@ The new bucket allocator can then be instantiated and used as a global allocator
 static ALLOCATOR: MyBucketAllocator = MyBucketAllocator::new();
 ```
 
-Buckets are checked for the best fit in order of specification. Full buckets are skipped.
+By default, buckets are checked in order of specification and the first bucket that fits the allocation is used. Full buckets are skipped. See [Bucket selection](#bucket-selection) for alternatives.
+
+Parameters don’t have to be literals. Any const expression works, so buckets can be sized to fit the types they are meant for:
+
+```rust
+use silly_alloc::bucket_allocator;
+
+struct Entity { id: u64, position: [f32; 3] }
+const MAX_ENTITIES: usize = 1024;
+
+#[bucket_allocator]
+struct MyBucketAllocator {
+    entities: Bucket<
+        SlotSize<{ core::mem::size_of::<Entity>() }>,
+        NumSlots<MAX_ENTITIES>,
+        Align<{ core::mem::align_of::<Entity>() }>
+    >,
+    overflow: Bucket<SlotSize<{ 4 * 1024 }>, NumSlots<16>, Align<64>>
+}
+```
+
+Alignments must evaluate to a power of two of at most [`MAX_ALIGN`] (64 KiB) and slot sizes to a non-zero multiple of their alignment, otherwise compilation fails. `sort_buckets` only works when all slot sizes and alignments are literals.
+
+The macro warns about buckets that come after a bucket that fits all of their allocations, as these only get used once the earlier bucket is full.
+
+The generated struct keeps the visibility, attributes and doc comments of the original struct, so a crate can export a configured allocator for others to install. For each bucket, a method of the same name and visibility returns a [`BucketView`], which tells how full the bucket is and whether a pointer belongs to it. Buckets can’t be named like the allocator’s own methods, such as `new` or `reset`.
+
+The generated struct implements `Debug`, unless the original struct already derives it.
+
+## Bucket selection
+
+The `selection` option controls the order in which buckets are checked for an allocation:
+
+- `first_fit` (default): In order of specification.
+- `best_fit`: From the smallest slot size to the biggest, so the allocation ends up in the smallest slot that fits.
+- `least_waste`: Like `best_fit`, but buckets with the same slot size are checked from the smallest alignment to the biggest. This keeps slots with big alignments available for allocations that need them.
+
+If the first bucket that fits is full, the allocation spills over into the next bucket that fits. With `spill = false`, the allocation fails instead:
+
+```rust
+use silly_alloc::bucket_allocator;
+
+#[bucket_allocator(selection = best_fit, spill = false)]
+struct MyBucketAllocator {
+    overflow: Bucket<SlotSize<64>, NumSlots<64>, Align<64>>,
+    vec2: Bucket<SlotSize<2>, NumSlots<128>, Align<2>>,
+}
+```
+
+## Spans
+
+A bucket only serves allocations up to its slot size. With `MaxSpan<N>`, an allocation may occupy up to `N` consecutive slots instead, so one bucket can serve a range of sizes:
+
+```rust
+use silly_alloc::bucket_allocator;
+
+#[bucket_allocator]
+struct MyBucketAllocator {
+    // Serves allocations of up to 128 bytes.
+    small: Bucket<SlotSize<32>, NumSlots<256>, Align<8>, MaxSpan<4>>,
+}
+```
+
+The number of slots an allocation occupies is derived from its layout when it is freed. Spans are not supported with the `arena` option.
+
+## Over-aligned allocations
+
+Usually, a bucket only serves allocations whose alignment is at most the bucket’s `Align`. Some slots happen to be at addresses with a bigger alignment, though. For example, every other slot of a `SlotSize<4>, Align<4>` bucket is 8-aligned. With `aligned_slot_search = true`, buckets look for such a slot for allocations with a bigger alignment, instead of leaving them to a bucket with a bigger alignment. This option is not supported with the `arena` option.
+
+## Quarantine
+
+A freed slot is usually the first one to be handed out again, so a write through a dangling pointer silently corrupts the next allocation. With `Quarantine<N>`, freed allocations are held back in a queue and their slots only become claimable again after `N` more frees:
+
+```rust
+use silly_alloc::bucket_allocator;
+
+#[bucket_allocator]
+struct MyBucketAllocator {
+    objects: Bucket<SlotSize<32>, NumSlots<256>, Align<8>, Quarantine<64>>,
+}
+```
+
+Combined with the `poison` feature, writes to a slot during its quarantine are detected when the slot is claimed again. Quarantines are not supported with the `arena` option.
+
+## Size classes
+
+Instead of declaring every bucket by hand, the macro can generate a series of buckets. Each generated bucket gets the biggest power of two that divides its slot size as its alignment (capped at 512) and enough slots to fill `bytes_per_class` bytes:
+
+```rust
+use silly_alloc::bucket_allocator;
+
+#[bucket_allocator(
+    size_classes = geometric(min = 8, max = 4096, ratio = 1.25),
+    bytes_per_class = 16384
+)]
+struct MyBucketAllocator {
+    // Buckets declared explicitly are checked before the generated ones.
+    tiny: Bucket<SlotSize<4>, NumSlots<128>, Align<4>>
+}
+```
+
+`geometric` sizes are rounded up to a multiple of 8. Alternatively, `powers_of_two(min = 8, max = 4096)` generates a bucket for each power of two in the range.
+
+## Growable buckets
+
+By default, every bucket reserves the memory for all of its slots up front, making the allocator as big as the sum of all its buckets. Alternatively, the buckets can carve their segments lazily from a [`BumpAllocatorArena`] as they fill up. `NumSlots<N>` then only is the maximum number of slots a bucket can grow to. The arena type is passed as the `arena` option and the arena itself to `with_arena()`:
+
+```rust,no_run
+use silly_alloc::bucket_allocator;
+
+const ARENA_SIZE: usize = 64 * 1024;
+static mut ARENA: [u8; ARENA_SIZE] = [0u8; ARENA_SIZE];
+
+#[bucket_allocator(arena = &'static [u8])]
+struct MyBucketAllocator {
+    vec2: Bucket<SlotSize<2>, NumSlots<128>, Align<2>>,
+    overflow: Bucket<SlotSize<64>, NumSlots<64>, Align<64>>
+}
+
+#[global_allocator]
+static ALLOCATOR: MyBucketAllocator = MyBucketAllocator::with_arena(unsafe { ARENA.as_slice() });
+```
+
+On WebAssembly, [`WasmMemoryArena`](crate::bump::wasm::WasmMemoryArena) can be used to let the buckets grow into the Wasm memory.
+
+## Without macros
+
+If a macro is not an option, e.g. in generic code, [`BucketAllocator`] takes a tuple of buckets as a type parameter instead. Each bucket is a [`BucketImpl`] of a slot type, which determines slot size and alignment, and the number of segments of 32 slots each:
+
+```rust
+use silly_alloc::bucket::{BucketAllocator, BucketImpl, SlotWithAlign8, SlotWithAlign64};
+
+type MyBucketAllocator = BucketAllocator<(
+    BucketImpl<SlotWithAlign8<8>, 4>,
+    BucketImpl<SlotWithAlign64<64>, 2>,
+)>;
+
+static ALLOCATOR: MyBucketAllocator = BucketAllocator::new((BucketImpl::new(), BucketImpl::new()));
+```
+
+## Object pools
+
+[`Pool<T, N>`](Pool) is a typed and safe wrapper around a single [`BucketImpl`], for when the values of one type should be stored separately instead of going through the global allocator. Values are moved into the pool with `alloc()` and their slot is returned once the [`PoolBox`] is dropped.
+
+[`HandlePool<T, N>`](HandlePool) hands out generational [`Handle`]s instead of references. A handle to a removed value is detected as stale, even if its slot has been reused in the meantime.
+
+## Resetting
+
+For allocations that all die at the same time, e.g. at the end of a frame or a request, the generated `reset()` method frees all allocations of all buckets at once. `reset_bucket(name)` does the same for a single bucket. Both take time proportional to the number of segments, not the number of live allocations. Like [`BumpAllocator::reset`], both are `unsafe`, as any remaining values are invalidated.
+
+## Listing allocations
+
+To find out what is still allocated, e.g. when hunting down leaks, the generated `for_each_live()` method calls a closure with the bucket name, address and slot size of every slot in use. It doesn’t allocate, so it can be used from within the allocator’s own app.
+
+## Statistics
+
+With `#[bucket_allocator(stats = true)]`, the allocator tracks how each bucket is being used and provides a `stats()` method that returns a [`BucketStats`] for every bucket. This can help with tuning the bucket configuration to the actual allocation behavior of an app.
 
 # Technical details
 
@@ -43,17 +199,28 @@ A bucket is defined by three parameters:
 - The number of items that fit in the bucket
 - An optional alignment constraint
 
-The speed of bucket allocators stems from the fact that all items in the bucket are the same size, and as such a simple bit mask is enough to track if a slot is in use or not. For simplicity, 32 slots are grouped into one segment, where a single `u32` is used to hold that bitmask. A bucket, as a consequence, is an array of segments. This also implies that `NumSlots<N>` will be rounded up to the next multiple of 32.
+The speed of bucket allocators stems from the fact that all items in the bucket are the same size, and as such a simple bit mask is enough to track if a slot is in use or not. For simplicity, 32 slots are grouped into one segment, where a single `u32` is used to hold that bitmask. This also implies that `NumSlots<N>` will be rounded up to the next multiple of 32. The bitmasks of all segments are stored in one array, separately from the slots. Bitmasks don’t need to be padded to the slots’ alignment that way, and looking for a free slot only has to scan a small, densely packed array.
+
+An empty bucket is all zeroes, so it can be created in a `const` context. Bucket allocators in a `static` end up in `.bss` and need no initialization at runtime.
 */
 
 use core::{
+    alloc::{GlobalAlloc, Layout},
+    cell::UnsafeCell,
     fmt::{Debug, Formatter},
     marker::PhantomData,
-    mem::{size_of, MaybeUninit},
+    mem::{align_of, size_of, MaybeUninit},
+    ptr::null_mut,
 };
 
+use crate::bump::{BumpAllocator, BumpAllocatorArena, Head};
+use crate::poison;
+
 pub use bytemuck::Zeroable;
 
+pub mod pool;
+pub use pool::{Handle, HandlePool, Pool, PoolBox, TypedSlot};
+
 // TODO: Implement thread-safe segments
 // #[cfg(target_feature = "feature")]
 #[derive(Clone, Copy)]
@@ -78,6 +245,15 @@ impl SegmentHeader {
         (slot_idx >> 5, slot_idx % 32)
     }
 
+    fn is_slot_set(&self, slot_idx: usize) -> bool {
+        let (arr_idx, bit_idx) = Self::slot_to_idx(slot_idx);
+        self.0[arr_idx] & (1 << (31 - bit_idx)) != 0
+    }
+
+    fn is_full(&self) -> bool {
+        self.0.iter().all(|&header| header == u32::MAX)
+    }
+
     fn set_slot(&mut self, slot_idx: usize) {
         let (arr_idx, bit_idx) = Self::slot_to_idx(slot_idx);
         self.0[arr_idx] |= 1 << (31 - bit_idx);
@@ -112,296 +288,1134 @@ const NUM_U32_PER_HEADER: usize = 1;
 pub const NUM_SLOTS_PER_SEGMENT: usize = NUM_U32_PER_HEADER * size_of::<u32>() * 8;
 pub const SEGMENT_HEADER_SIZE: usize = NUM_U32_PER_HEADER * size_of::<u32>();
 
-pub trait Slot: Copy + Default {
+pub trait Slot: Zeroable {
     fn get(&self) -> *const u8;
     fn size() -> usize;
 }
 
+/// Marker type to look up the slot type for alignment `A` via [`SupportedAlignment`].
+pub struct AlignTo<const A: usize>;
+
+/// Implemented by [`AlignTo<A>`] for every alignment `A` that buckets support, which are all powers of two up to [`MAX_ALIGN`].
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not a supported bucket alignment",
+    label = "bucket alignments must be powers of two up to 65536"
+)]
+pub trait SupportedAlignment {
+    /// A zero-sized type with alignment `A`.
+    type Marker: Copy;
+    /// Slot type of size `N` with this alignment.
+    type Slot<const N: usize>: Slot;
+}
+
+/// The biggest supported slot alignment, which is the size of a WebAssembly page.
+pub const MAX_ALIGN: usize = 65536;
+
+/// A slot of size `N` with alignment `A`.
 #[derive(Clone, Copy)]
-pub struct Segment<S: Slot> {
-    header: SegmentHeader,
-    slots: [S; NUM_SLOTS_PER_SEGMENT],
+#[repr(C)]
+pub struct SlotWithAlign<const A: usize, const N: usize>
+where
+    AlignTo<A>: SupportedAlignment,
+{
+    _align: [<AlignTo<A> as SupportedAlignment>::Marker; 0],
+    data: [u8; N],
 }
 
-impl<S: Slot> Segment<S> {
-    fn new() -> Self {
-        Segment {
-            header: SegmentHeader::new(),
-            slots: [S::default(); NUM_SLOTS_PER_SEGMENT],
+impl<const A: usize, const N: usize> SlotWithAlign<A, N>
+where
+    AlignTo<A>: SupportedAlignment,
+{
+    pub const fn new() -> Self {
+        SlotWithAlign {
+            _align: [],
+            data: [0u8; N],
         }
     }
-
-    fn get_slot(&self, slot_idx: usize) -> *const u8 {
-        self.slots[slot_idx].get()
-    }
 }
 
-impl<S: Slot> Default for Segment<S> {
+impl<const A: usize, const N: usize> Default for SlotWithAlign<A, N>
+where
+    AlignTo<A>: SupportedAlignment,
+{
     fn default() -> Self {
         Self::new()
     }
 }
 
-unsafe impl<S: Slot + Zeroable> Zeroable for Segment<S> {}
-
-impl<S: Slot> Debug for Segment<S> {
+impl<const A: usize, const N: usize> Debug for SlotWithAlign<A, N>
+where
+    AlignTo<A>: SupportedAlignment,
+{
     fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
-        f.debug_struct("Segment")
-            .field("header", &self.header)
-            .finish()?;
-        Ok(())
+        f.debug_tuple("SlotWithAlign").field(&self.data).finish()
     }
 }
 
-macro_rules! align_type {
-    ($name:ident, $n:expr) => {
-        #[derive(Debug, Clone, Copy)]
-        #[repr(C, align($n))]
-        pub struct $name<const N: usize>([u8; N]);
-        impl<const N: usize> $name<N> {
-            pub const fn new() -> Self {
-                $name([0u8; N])
-            }
-        }
+impl<const A: usize, const N: usize> Slot for SlotWithAlign<A, N>
+where
+    AlignTo<A>: SupportedAlignment,
+{
+    fn get(&self) -> *const u8 {
+        self.data.as_ptr()
+    }
 
-        impl<const N: usize> Default for $name<N> {
-            fn default() -> Self {
-                Self::new()
-            }
-        }
+    fn size() -> usize {
+        N
+    }
+}
 
-        impl<const N: usize> Slot for $name<N> {
-            fn get(&self) -> *const u8 {
-                self.0.as_ptr()
-            }
+unsafe impl<const A: usize, const N: usize> Zeroable for SlotWithAlign<A, N> where
+    AlignTo<A>: SupportedAlignment
+{
+}
 
-            fn size() -> usize {
-                N
-            }
+/// The slot type with alignment `A` and size `N`.
+///
+/// This allows picking a slot type from const expressions, e.g. `SlotFor<{ align_of::<T>() }, { size_of::<T>() }>`.
+pub type SlotFor<const A: usize, const N: usize> = <AlignTo<A> as SupportedAlignment>::Slot<N>;
+
+/// Returns the number of segments needed to hold `num_slots` slots.
+pub const fn num_segments_for_slots(num_slots: usize) -> usize {
+    num_slots.div_ceil(NUM_SLOTS_PER_SEGMENT)
+}
+
+/// Strategy to pick a bucket for an allocation, as set by the `selection` option of [`bucket_allocator`](crate::bucket_allocator).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Selection {
+    /// Buckets are checked in order of declaration.
+    FirstFit,
+    /// Buckets are checked from the smallest slot size to the biggest. Buckets of the same slot size are checked in order of declaration.
+    BestFit,
+    /// Like `BestFit`, but buckets of the same slot size are checked from the smallest alignment to the biggest, so slots with a big alignment stay available for the allocations that need them.
+    LeastWaste,
+}
+
+impl Selection {
+    const fn goes_before(
+        self,
+        (size_a, align_a): (usize, usize),
+        (size_b, align_b): (usize, usize),
+    ) -> bool {
+        match self {
+            Selection::FirstFit => false,
+            Selection::BestFit => size_a < size_b,
+            Selection::LeastWaste => size_a < size_b || (size_a == size_b && align_a < align_b),
+        }
+    }
+}
+
+/// Returns the order in which buckets with the given slot sizes and alignments are checked.
+pub const fn selection_order<const N: usize>(
+    params: [(usize, usize); N],
+    selection: Selection,
+) -> [usize; N] {
+    let mut order = [0; N];
+    let mut i = 0;
+    while i < N {
+        order[i] = i;
+        i += 1;
+    }
+    // Insertion sort, as it is stable and simple enough for a `const fn`.
+    let mut i = 1;
+    while i < N {
+        let mut j = i;
+        while j > 0 && selection.goes_before(params[order[j]], params[order[j - 1]]) {
+            let tmp = order[j];
+            order[j] = order[j - 1];
+            order[j - 1] = tmp;
+            j -= 1;
         }
+        i += 1;
+    }
+    order
+}
 
-        unsafe impl<const N: usize> Zeroable for $name<N> {}
+macro_rules! alignments {
+    ($($n:literal),*) => {
+        $(
+            const _: () = {
+                #[derive(Debug, Clone, Copy)]
+                #[repr(align($n))]
+                pub struct Marker;
+
+                impl SupportedAlignment for AlignTo<$n> {
+                    type Marker = Marker;
+                    type Slot<const N: usize> = SlotWithAlign<$n, N>;
+                }
+            };
+        )*
     };
 }
 
-align_type!(SlotWithAlign1, 1);
-align_type!(SlotWithAlign2, 2);
-align_type!(SlotWithAlign4, 4);
-align_type!(SlotWithAlign8, 8);
-align_type!(SlotWithAlign16, 16);
-align_type!(SlotWithAlign32, 32);
-align_type!(SlotWithAlign64, 64);
-align_type!(SlotWithAlign128, 128);
-align_type!(SlotWithAlign256, 256);
-align_type!(SlotWithAlign512, 512);
-
-#[derive(Debug, Clone, Copy)]
-pub struct BucketImpl<S: Slot, const N: usize> {
-    // Deeply saddened that I had to introduce a bool flag here. There just currently is no way initializing this array in a const way, so I have to defer it to runtime and track when it has been done. This flag can be removed when at least one of these options is available in stable rust:
-    // - const fn can be in traits
-    // - core::ptr::write_bytes is const
-    // - core::mem::MaybeUninit.zeroed() is const (although that probably relies on previous point)
-    is_init: bool,
-    segments: MaybeUninit<[Segment<S>; N]>,
-}
-
-impl<S: Slot, const NUM_SEGMENTS: usize> BucketImpl<S, NUM_SEGMENTS> {
-    pub const fn new() -> Self {
-        Self {
-            is_init: false,
-            segments: MaybeUninit::uninit(),
+alignments!(1, 2, 4, 8, 16, 32, 64, 128, 256, 512, 1024, 2048, 4096, 8192, 16384, 32768, 65536);
+
+// Shorthands for the common alignments.
+pub type SlotWithAlign1<const N: usize> = SlotWithAlign<1, N>;
+pub type SlotWithAlign2<const N: usize> = SlotWithAlign<2, N>;
+pub type SlotWithAlign4<const N: usize> = SlotWithAlign<4, N>;
+pub type SlotWithAlign8<const N: usize> = SlotWithAlign<8, N>;
+pub type SlotWithAlign16<const N: usize> = SlotWithAlign<16, N>;
+pub type SlotWithAlign32<const N: usize> = SlotWithAlign<32, N>;
+pub type SlotWithAlign64<const N: usize> = SlotWithAlign<64, N>;
+pub type SlotWithAlign128<const N: usize> = SlotWithAlign<128, N>;
+pub type SlotWithAlign256<const N: usize> = SlotWithAlign<256, N>;
+pub type SlotWithAlign512<const N: usize> = SlotWithAlign<512, N>;
+
+/// The slots of a single segment.
+pub type SegmentSlots<S> = [S; NUM_SLOTS_PER_SEGMENT];
+
+fn global_to_local(slot_idx: usize) -> (usize, usize) {
+    let seg_idx = slot_idx / NUM_SLOTS_PER_SEGMENT;
+    let slot_idx = slot_idx % NUM_SLOTS_PER_SEGMENT;
+    (seg_idx, slot_idx)
+}
+
+fn first_free_slot_idx(headers: &[SegmentHeader]) -> Option<usize> {
+    headers.iter().enumerate().find_map(|(seg_idx, header)| {
+        Some(seg_idx * NUM_SLOTS_PER_SEGMENT + header.first_free_slot_idx()?)
+    })
+}
+
+/// Calls `f` with the index of every slot in use.
+fn for_each_set_slot_idx(headers: &[SegmentHeader], mut f: impl FnMut(usize)) {
+    for (seg_idx, header) in headers.iter().enumerate() {
+        for local_idx in 0..NUM_SLOTS_PER_SEGMENT {
+            if header.is_slot_set(local_idx) {
+                f(seg_idx * NUM_SLOTS_PER_SEGMENT + local_idx);
+            }
         }
-        // unsafe { MaybeUninit::zeroed().assume_init() }
     }
+}
 
-    pub fn ensure_init(&mut self) {
-        if self.is_init {
-            return;
+/// Finds `num_slots` consecutive free slots, which may cross segment boundaries. Spans only start at slots for which `is_start` returns true.
+fn first_free_span_idx(
+    headers: &[SegmentHeader],
+    num_slots: usize,
+    is_start: impl Fn(usize) -> bool,
+) -> Option<usize> {
+    let mut span_start = 0;
+    let mut span_len = 0;
+    for (seg_idx, header) in headers.iter().enumerate() {
+        if header.is_full() {
+            span_len = 0;
+            continue;
         }
-        unsafe {
-            core::ptr::write_bytes(self.segments.as_mut_ptr(), 0u8, 1);
+        for local_idx in 0..NUM_SLOTS_PER_SEGMENT {
+            let slot_idx = seg_idx * NUM_SLOTS_PER_SEGMENT + local_idx;
+            if header.is_slot_set(local_idx) {
+                span_len = 0;
+                continue;
+            }
+            if span_len == 0 {
+                if !is_start(slot_idx) {
+                    continue;
+                }
+                span_start = slot_idx;
+            }
+            span_len += 1;
+            if span_len == num_slots {
+                return Some(span_start);
+            }
         }
-        self.is_init = true
     }
+    None
+}
 
-    fn get_segments(&self) -> &[Segment<S>; NUM_SEGMENTS] {
-        assert!(self.is_init);
-        unsafe { self.segments.assume_init_ref() }
+/// Holds back freed spans of a [`BucketImpl`], given as `(slot_idx, num_slots)`, before they become claimable again.
+///
+/// `()` is the empty quarantine that releases every span right away and takes up no space.
+pub trait QuarantineQueue: Copy + Zeroable {
+    /// Adds a freed span to the queue. Returns the span that has to be released now, if any.
+    fn push(&mut self, span: (usize, usize)) -> Option<(usize, usize)>;
+    /// Removes the oldest span from the queue.
+    fn pop(&mut self) -> Option<(usize, usize)>;
+    /// Whether the slot is part of a span in the queue.
+    fn contains(&self, slot_idx: usize) -> bool;
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
     }
+    fn clear(&mut self);
+}
 
-    fn get_segments_mut(&mut self) -> &mut [Segment<S>; NUM_SEGMENTS] {
-        assert!(self.is_init);
-        unsafe { self.segments.assume_init_mut() }
+impl QuarantineQueue for () {
+    fn push(&mut self, span: (usize, usize)) -> Option<(usize, usize)> {
+        Some(span)
     }
 
-    pub fn claim_first_available_slot(&mut self) -> Option<*const u8> {
-        for seg in self.get_segments_mut().iter_mut() {
-            let Some(slot_idx) = seg.header.first_free_slot_idx() else {
-                continue;
-            };
-            seg.header.set_slot(slot_idx);
-            return Some(seg.get_slot(slot_idx));
-        }
+    fn pop(&mut self) -> Option<(usize, usize)> {
         None
     }
 
-    fn global_to_local(&self, slot_idx: usize) -> (usize, usize) {
-        let seg_idx = slot_idx / NUM_SLOTS_PER_SEGMENT;
-        let slot_idx = slot_idx % NUM_SLOTS_PER_SEGMENT;
-        (seg_idx, slot_idx)
+    fn contains(&self, _slot_idx: usize) -> bool {
+        false
     }
 
-    pub fn get_slot(&self, slot_idx: usize) -> *const u8 {
-        let (seg_idx, slot_idx) = self.global_to_local(slot_idx);
-        self.get_segments()[seg_idx].get_slot(slot_idx)
+    fn len(&self) -> usize {
+        0
     }
 
-    pub fn set_slot(&mut self, slot_idx: usize) {
-        let (seg_idx, slot_idx) = self.global_to_local(slot_idx);
-        self.get_segments_mut()[seg_idx].header.set_slot(slot_idx);
-    }
+    fn clear(&mut self) {}
+}
 
-    pub fn unset_slot(&mut self, slot_idx: usize) {
-        let (seg_idx, slot_idx) = self.global_to_local(slot_idx);
-        self.get_segments_mut()[seg_idx].header.unset_slot(slot_idx);
+/// A quarantine that holds back the last `N` freed spans. Once it is full, each free releases the oldest span.
+#[derive(Clone, Copy)]
+pub struct QuarantineFifo<const N: usize> {
+    entries: [(usize, usize); N],
+    start: usize,
+    len: usize,
+}
+
+unsafe impl<const N: usize> Zeroable for QuarantineFifo<N> {}
+
+impl<const N: usize> QuarantineQueue for QuarantineFifo<N> {
+    fn push(&mut self, span: (usize, usize)) -> Option<(usize, usize)> {
+        if N == 0 {
+            return Some(span);
+        }
+        if self.len < N {
+            self.entries[(self.start + self.len) % N] = span;
+            self.len += 1;
+            return None;
+        }
+        let oldest = core::mem::replace(&mut self.entries[self.start], span);
+        self.start = (self.start + 1) % N;
+        Some(oldest)
     }
 
-    // No pointer is being dereferenced, only math is done on the addresses. But Clippy is kicking off.
-    #[allow(clippy::not_unsafe_ptr_arg_deref)]
-    pub fn slot_idx_for_ptr(&self, ptr: *const u8) -> Option<usize> {
-        let seg_stride = size_of::<Segment<S>>();
-        let slot_stride = size_of::<S>();
-
-        let start = self.segments.as_ptr() as *const u8;
-        let offset = unsafe { ptr.offset_from(start) };
-        // Conversion to usize will only succeed for positive numbers.
-        // If it's negative, ptr is in previous segment.
-        let offset = usize::try_from(offset).ok()?;
-        let seg_idx = offset / seg_stride;
-        let offset = offset % seg_stride;
-        let slot_idx = offset / slot_stride;
-        if seg_idx >= NUM_SEGMENTS || slot_idx > NUM_SLOTS_PER_SEGMENT {
+    fn pop(&mut self) -> Option<(usize, usize)> {
+        if self.len == 0 {
             return None;
         }
+        let oldest = self.entries[self.start];
+        self.start = (self.start + 1) % N;
+        self.len -= 1;
+        Some(oldest)
+    }
 
-        Some(seg_idx * NUM_SLOTS_PER_SEGMENT + slot_idx)
+    fn contains(&self, slot_idx: usize) -> bool {
+        (0..self.len).any(|i| {
+            let (start, num_slots) = self.entries[(self.start + i) % N];
+            (start..start + num_slots).contains(&slot_idx)
+        })
     }
-}
 
-unsafe impl<S: Slot, const N: usize> Zeroable for BucketImpl<S, N> {}
+    fn len(&self) -> usize {
+        self.len
+    }
 
-impl<S: Slot, const N: usize> Default for BucketImpl<S, N> {
-    fn default() -> Self {
-        BucketImpl::<S, N>::new()
+    fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
     }
 }
 
-pub struct SlotSize<const N: usize>;
-pub struct NumSlots<const N: usize>;
-pub struct Align<const N: usize>;
-
-pub struct Bucket<S, N, A = Align<1>>(PhantomData<S>, PhantomData<N>, PhantomData<A>);
-
-#[cfg(test)]
-mod test {
-    use super::*;
+/// A bucket that stores all of its slots inline.
+///
+/// The headers of all segments are packed into one array that is separate from the slots. That way, headers don’t get padded to the slots’ alignment, and looking for a free slot only scans a small, contiguous bitmap.
+///
+/// With a [`QuarantineFifo<Q>`] as `Q`, freed slots are quarantined: They only become claimable again after `Q` other frees, so a use after free doesn’t immediately corrupt the next allocation. With the `poison` feature, writes to quarantined slots are detected once they are claimed again.
+#[derive(Clone, Copy)]
+pub struct BucketImpl<S: Slot, const N: usize, Q: QuarantineQueue = ()> {
+    headers: [SegmentHeader; N],
+    quarantine: Q,
+    /// Slots from this index on have never been claimed, so they still hold zeroes instead of [`poison::FREED`].
+    #[cfg(feature = "poison")]
+    claimed_until: usize,
+    slots: [SegmentSlots<S>; N],
+}
 
-    use core::alloc::{GlobalAlloc, Layout};
+impl<S: Slot, const NUM_SEGMENTS: usize, Q: QuarantineQueue> BucketImpl<S, NUM_SEGMENTS, Q> {
+    /// Creates an empty bucket.
+    ///
+    /// The bucket is all zeroes, so a `static` bucket ends up in `.bss` and requires no initialization at runtime.
+    pub const fn new() -> Self {
+        // All-zero is a valid state for headers (all slots free) and for slots (`Slot: Zeroable`).
+        unsafe { MaybeUninit::zeroed().assume_init() }
+    }
 
-    use anyhow::Result;
+    pub fn claim_first_available_slot(&mut self) -> Option<*const u8> {
+        let slot_idx = first_free_slot_idx(&self.headers)?;
+        self.set_slot(slot_idx);
+        self.poison_claimed_slot(slot_idx);
+        Some(self.get_slot(slot_idx))
+    }
 
-    use silly_alloc_macros::bucket_allocator;
+    /// Claims `num_slots` consecutive slots for a single allocation that is bigger than a slot. As all slots are stored in one array, spans can cross segment boundaries.
+    pub fn claim_span(&mut self, num_slots: usize) -> Option<*const u8> {
+        self.claim_aligned_span(num_slots, align_of::<S>())
+    }
 
-    #[bucket_allocator]
-    struct MyBucketAllocator {
-        vec2: Bucket<SlotSize<2>, NumSlots<32>, Align<2>>,
-        vec4: Bucket<SlotSize<4>, NumSlots<32>, Align<4>>,
-        vec8: Bucket<SlotSize<8>, NumSlots<32>, Align<8>>,
+    /// Claims the first free slot whose address is a multiple of `align`, even if `align` is bigger than the alignment of the slot type.
+    pub fn claim_first_aligned_slot(&mut self, align: usize) -> Option<*const u8> {
+        self.claim_aligned_span(1, align)
     }
 
-    #[test]
-    fn next_in_bucket() -> Result<()> {
-        let b = MyBucketAllocator::new();
-        unsafe {
-            let ptr1 = b.alloc(Layout::from_size_align(2, 1)?);
-            let ptr2 = b.alloc(Layout::from_size_align(2, 1)?);
-            assert!(!ptr1.is_null());
-            assert!(!ptr2.is_null());
-            assert_eq!(ptr1.offset(2), ptr2);
+    /// Like [`claim_span`](Self::claim_span), but the span has to start at an address that is a multiple of `align`.
+    pub fn claim_aligned_span(&mut self, num_slots: usize, align: usize) -> Option<*const u8> {
+        let slot_idx = first_free_span_idx(&self.headers, num_slots, |slot_idx| {
+            (self.get_slot(slot_idx) as usize).is_multiple_of(align)
+        })?;
+        for idx in slot_idx..slot_idx + num_slots {
+            self.set_slot(idx);
+            self.poison_claimed_slot(idx);
         }
-        Ok(())
+        Some(self.get_slot(slot_idx))
     }
 
-    #[test]
-    fn full_bucket() -> Result<()> {
-        #[bucket_allocator]
-        struct MyBucketAllocator {
-            vec2: Bucket<SlotSize<2>, NumSlots<32>, Align<2>>,
+    /// Frees the `num_slots` consecutive slots starting at `slot_idx`, as claimed by [`claim_span`](Self::claim_span).
+    pub fn unset_span(&mut self, slot_idx: usize, num_slots: usize) {
+        for idx in slot_idx..slot_idx + num_slots {
+            unsafe { poison::freed(self.slot_ptr_mut(idx), size_of::<S>()) };
         }
-        unsafe {
-            let b = MyBucketAllocator::new();
-            let l = Layout::from_size_align(2, 1)?;
-            for _ in 0..32 {
-                b.alloc(l);
-            }
-            let ptr = b.alloc(l);
-            assert!(ptr.is_null());
+        if let Some((slot_idx, num_slots)) = self.quarantine.push((slot_idx, num_slots)) {
+            self.release_span(slot_idx, num_slots);
         }
-        Ok(())
     }
 
-    #[test]
-    fn reuse() -> Result<()> {
-        let b = MyBucketAllocator::new();
-        unsafe {
-            let layout = Layout::from_size_align(2, 1)?;
-            let ptr1 = b.alloc(layout);
-            let ptr2 = b.alloc(layout);
-            let ptr3 = b.alloc(layout);
-            assert_eq!(ptr1.offset(2), ptr2);
-            assert_eq!(ptr2.offset(2), ptr3);
-            b.dealloc(ptr2, layout);
-            let ptr4 = b.alloc(layout);
-            assert_eq!(ptr2, ptr4);
+    /// Makes all quarantined slots claimable again.
+    pub fn flush_quarantine(&mut self) {
+        while let Some((slot_idx, num_slots)) = self.quarantine.pop() {
+            self.release_span(slot_idx, num_slots);
         }
-        Ok(())
     }
 
-    #[test]
-    fn bucket_overflow() -> Result<()> {
-        let b = MyBucketAllocator::new();
-        unsafe {
-            let layout = Layout::from_size_align(2, 1)?;
-            // Fill 2 byte bucket
-            for _ in 0..32 {
-                b.alloc(layout);
-            }
-            let ptr1 = b.alloc(Layout::from_size_align(4, 1)?);
-            let ptr2 = b.alloc(layout);
-            assert_eq!(ptr1.offset(4), ptr2);
+    fn release_span(&mut self, slot_idx: usize, num_slots: usize) {
+        for idx in slot_idx..slot_idx + num_slots {
+            let (seg_idx, local_idx) = global_to_local(idx);
+            self.headers[seg_idx].unset_slot(local_idx);
         }
-        Ok(())
     }
 
-    #[test]
-    fn alignment() -> Result<()> {
-        let mut b = MyBucketAllocator::new();
-        unsafe {
-            let layout = Layout::from_size_align(2, 8)?;
-            let ptr1 = b.alloc(layout);
-            // Alignment requirement should force the allocation into the last bucket desipte its size
-            assert!(ptr1 >= &mut b.2 as *mut _ as *mut u8);
-        }
-        Ok(())
+    pub fn get_slot(&self, slot_idx: usize) -> *const u8 {
+        let (seg_idx, slot_idx) = global_to_local(slot_idx);
+        self.slots[seg_idx][slot_idx].get()
     }
 
-    #[test]
-    fn alignment_fail() -> Result<()> {
-        let b = MyBucketAllocator::new();
-        unsafe {
-            let layout = Layout::from_size_align(2, 32)?;
-            let ptr1 = b.alloc(layout);
-            assert!(ptr1.is_null());
+    pub fn set_slot(&mut self, slot_idx: usize) {
+        let (seg_idx, slot_idx) = global_to_local(slot_idx);
+        self.headers[seg_idx].set_slot(slot_idx);
+    }
+
+    /// Whether the slot is claimed. Quarantined slots count as claimed.
+    pub fn is_slot_set(&self, slot_idx: usize) -> bool {
+        let (seg_idx, slot_idx) = global_to_local(slot_idx);
+        self.headers[seg_idx].is_slot_set(slot_idx)
+    }
+
+    pub fn unset_slot(&mut self, slot_idx: usize) {
+        self.unset_span(slot_idx, 1);
+    }
+
+    /// Marks all slots as free, including quarantined ones. Takes time proportional to the number of segments, not the number of live allocations.
+    pub fn reset(&mut self) {
+        if cfg!(feature = "poison") {
+            let headers = self.headers;
+            for_each_set_slot_idx(&headers, |slot_idx| unsafe {
+                poison::freed(self.slot_ptr_mut(slot_idx), size_of::<S>())
+            });
+        }
+        self.headers = [SegmentHeader::new(); NUM_SEGMENTS];
+        self.quarantine.clear();
+    }
+
+    fn slot_ptr_mut(&mut self, slot_idx: usize) -> *mut u8 {
+        let (seg_idx, slot_idx) = global_to_local(slot_idx);
+        &mut self.slots[seg_idx][slot_idx] as *mut S as *mut u8
+    }
+
+    fn poison_claimed_slot(&mut self, slot_idx: usize) {
+        #[cfg(feature = "poison")]
+        if slot_idx >= self.claimed_until {
+            for idx in self.claimed_until..=slot_idx {
+                unsafe { poison::freed(self.slot_ptr_mut(idx), size_of::<S>()) };
+            }
+            self.claimed_until = slot_idx + 1;
+        }
+        let ptr = self.slot_ptr_mut(slot_idx);
+        unsafe {
+            poison::check_freed(ptr, size_of::<S>());
+            poison::fresh(ptr, size_of::<S>());
+        }
+    }
+
+    /// Calls `f` with the address of every slot in use. Quarantined slots are not in use.
+    pub fn for_each_live_slot(&self, mut f: impl FnMut(*const u8)) {
+        for_each_set_slot_idx(&self.headers, |slot_idx| {
+            if !self.quarantine.contains(slot_idx) {
+                f(self.get_slot(slot_idx))
+            }
+        });
+    }
+
+    pub fn slot_idx_for_ptr(&self, ptr: *const u8) -> Option<usize> {
+        // All slots are in one consecutive array, so this is a simple range check.
+        let offset = (ptr as usize).checked_sub(self.slots.as_ptr() as usize)?;
+        let slot_idx = offset / size_of::<S>();
+        if slot_idx >= NUM_SEGMENTS * NUM_SLOTS_PER_SEGMENT {
+            return None;
+        }
+        Some(slot_idx)
+    }
+}
+
+unsafe impl<S: Slot, const N: usize, Q: QuarantineQueue> Zeroable for BucketImpl<S, N, Q> {}
+
+impl<S: Slot, const N: usize, Q: QuarantineQueue> Default for BucketImpl<S, N, Q> {
+    fn default() -> Self {
+        BucketImpl::<S, N, Q>::new()
+    }
+}
+
+impl<S: Slot, const N: usize, Q: QuarantineQueue> Debug for BucketImpl<S, N, Q> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("BucketImpl")
+            .field("headers", &self.headers)
+            .field("quarantined", &self.quarantine.len())
+            .finish()
+    }
+}
+
+/// A bucket whose segments are carved from a [`BumpAllocatorArena`] on demand.
+///
+/// In contrast to [`BucketImpl`], the slots are not stored inline. Only the headers and a pointer to the slots of each segment are kept, and a new segment is allocated from the arena once all previous segments are full. `N` is the maximum number of segments the bucket can grow to.
+pub struct ArenaBucketImpl<S: Slot, const N: usize> {
+    headers: [SegmentHeader; N],
+    segments: [*mut SegmentSlots<S>; N],
+    num_segments: usize,
+}
+
+impl<S: Slot, const NUM_SEGMENTS: usize> ArenaBucketImpl<S, NUM_SEGMENTS> {
+    pub const fn new() -> Self {
+        Self {
+            headers: [SegmentHeader::new(); NUM_SEGMENTS],
+            segments: [null_mut(); NUM_SEGMENTS],
+            num_segments: 0,
+        }
+    }
+
+    /// Returns the number of segments that have been carved from the arena so far.
+    pub fn num_segments(&self) -> usize {
+        self.num_segments
+    }
+
+    fn grow<M: BumpAllocatorArena, H: Head + Default>(
+        &mut self,
+        arena: &BumpAllocator<'_, M, H>,
+    ) -> Option<usize> {
+        if self.num_segments >= NUM_SEGMENTS {
+            return None;
+        }
+        let slots =
+            unsafe { arena.alloc(Layout::new::<SegmentSlots<S>>()) } as *mut SegmentSlots<S>;
+        if slots.is_null() {
+            return None;
+        }
+        // Slots fresh from the arena could hold anything, so they need to be marked as freed to not be reported as written to.
+        unsafe { poison::freed(slots as *mut u8, size_of::<SegmentSlots<S>>()) };
+        let seg_idx = self.num_segments;
+        self.segments[seg_idx] = slots;
+        self.headers[seg_idx] = SegmentHeader::new();
+        self.num_segments += 1;
+        Some(seg_idx)
+    }
+
+    pub fn claim_first_available_slot<M: BumpAllocatorArena, H: Head + Default>(
+        &mut self,
+        arena: &BumpAllocator<'_, M, H>,
+    ) -> Option<*const u8> {
+        let slot_idx = match first_free_slot_idx(&self.headers[0..self.num_segments]) {
+            Some(slot_idx) => slot_idx,
+            None => self.grow(arena)? * NUM_SLOTS_PER_SEGMENT,
+        };
+        self.set_slot(slot_idx);
+        let ptr = self.slot_ptr_mut(slot_idx);
+        unsafe {
+            poison::check_freed(ptr, size_of::<S>());
+            poison::fresh(ptr, size_of::<S>());
+        }
+        Some(self.get_slot(slot_idx))
+    }
+
+    pub fn get_slot(&self, slot_idx: usize) -> *const u8 {
+        let (seg_idx, slot_idx) = global_to_local(slot_idx);
+        assert!(seg_idx < self.num_segments);
+        unsafe { (*self.segments[seg_idx])[slot_idx].get() }
+    }
+
+    pub fn set_slot(&mut self, slot_idx: usize) {
+        let (seg_idx, slot_idx) = global_to_local(slot_idx);
+        self.headers[seg_idx].set_slot(slot_idx);
+    }
+
+    pub fn unset_slot(&mut self, slot_idx: usize) {
+        unsafe { poison::freed(self.slot_ptr_mut(slot_idx), size_of::<S>()) };
+        let (seg_idx, slot_idx) = global_to_local(slot_idx);
+        self.headers[seg_idx].unset_slot(slot_idx);
+    }
+
+    /// Marks all slots as free. Takes time proportional to the number of segments, not the number of live allocations.
+    pub fn reset(&mut self) {
+        if cfg!(feature = "poison") {
+            for slot_idx in 0..self.num_segments * NUM_SLOTS_PER_SEGMENT {
+                let (seg_idx, local_idx) = global_to_local(slot_idx);
+                if self.headers[seg_idx].is_slot_set(local_idx) {
+                    self.unset_slot(slot_idx);
+                }
+            }
+        }
+        self.headers = [SegmentHeader::new(); NUM_SEGMENTS];
+    }
+
+    fn slot_ptr_mut(&mut self, slot_idx: usize) -> *mut u8 {
+        let (seg_idx, slot_idx) = global_to_local(slot_idx);
+        assert!(seg_idx < self.num_segments);
+        unsafe { (self.segments[seg_idx] as *mut S).add(slot_idx) as *mut u8 }
+    }
+
+    /// Calls `f` with the address of every slot in use.
+    pub fn for_each_live_slot(&self, mut f: impl FnMut(*const u8)) {
+        for_each_set_slot_idx(&self.headers[0..self.num_segments], |slot_idx| {
+            f(self.get_slot(slot_idx))
+        });
+    }
+
+    pub fn slot_idx_for_ptr(&self, ptr: *const u8) -> Option<usize> {
+        // Other buckets carve from the same arena, so segments are not necessarily adjacent.
+        self.segments[0..self.num_segments]
+            .iter()
+            .enumerate()
+            .find_map(|(seg_idx, &slots)| {
+                let offset = (ptr as usize).checked_sub(slots as usize)?;
+                let slot_idx = offset / size_of::<S>();
+                if slot_idx >= NUM_SLOTS_PER_SEGMENT {
+                    return None;
+                }
+                Some(seg_idx * NUM_SLOTS_PER_SEGMENT + slot_idx)
+            })
+    }
+}
+
+impl<S: Slot, const N: usize> Default for ArenaBucketImpl<S, N> {
+    fn default() -> Self {
+        ArenaBucketImpl::<S, N>::new()
+    }
+}
+
+impl<S: Slot, const N: usize> Debug for ArenaBucketImpl<S, N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ArenaBucketImpl")
+            .field("headers", &&self.headers[0..self.num_segments])
+            .finish()
+    }
+}
+
+/// A set of buckets that can serve allocations.
+///
+/// This is implemented for [`BucketImpl`] and for tuples of up to 12 `Buckets`, which are checked in order. See [`BucketAllocator`].
+pub trait Buckets {
+    /// Allocates from the first bucket that fits `layout` and is not full. Returns a null pointer if there is no such bucket.
+    fn alloc(&mut self, layout: Layout) -> *mut u8;
+    /// Returns `ptr` to the bucket it was allocated from. Returns `false` if `ptr` doesn’t belong to any of the buckets.
+    fn dealloc(&mut self, ptr: *mut u8, layout: Layout) -> bool;
+}
+
+impl<S: Slot, const N: usize, Q: QuarantineQueue> Buckets for BucketImpl<S, N, Q> {
+    fn alloc(&mut self, layout: Layout) -> *mut u8 {
+        if layout.size() > S::size() || layout.align() > align_of::<S>() {
+            return null_mut();
+        }
+        self.claim_first_available_slot()
+            .map_or(null_mut(), |ptr| ptr as *mut u8)
+    }
+
+    fn dealloc(&mut self, ptr: *mut u8, _layout: Layout) -> bool {
+        let Some(slot_idx) = self.slot_idx_for_ptr(ptr) else {
+            return false;
+        };
+        self.unset_slot(slot_idx);
+        true
+    }
+}
+
+macro_rules! tuple_buckets {
+    ($($name:ident: $idx:tt),+) => {
+        impl<$($name: Buckets),+> Buckets for ($($name,)+) {
+            fn alloc(&mut self, layout: Layout) -> *mut u8 {
+                $(
+                    let ptr = self.$idx.alloc(layout);
+                    if !ptr.is_null() {
+                        return ptr;
+                    }
+                )+
+                null_mut()
+            }
+
+            fn dealloc(&mut self, ptr: *mut u8, layout: Layout) -> bool {
+                $(
+                    if self.$idx.dealloc(ptr, layout) {
+                        return true;
+                    }
+                )+
+                false
+            }
+        }
+    };
+}
+
+tuple_buckets!(B0: 0);
+tuple_buckets!(B0: 0, B1: 1);
+tuple_buckets!(B0: 0, B1: 1, B2: 2);
+tuple_buckets!(B0: 0, B1: 1, B2: 2, B3: 3);
+tuple_buckets!(B0: 0, B1: 1, B2: 2, B3: 3, B4: 4);
+tuple_buckets!(B0: 0, B1: 1, B2: 2, B3: 3, B4: 4, B5: 5);
+tuple_buckets!(B0: 0, B1: 1, B2: 2, B3: 3, B4: 4, B5: 5, B6: 6);
+tuple_buckets!(B0: 0, B1: 1, B2: 2, B3: 3, B4: 4, B5: 5, B6: 6, B7: 7);
+tuple_buckets!(B0: 0, B1: 1, B2: 2, B3: 3, B4: 4, B5: 5, B6: 6, B7: 7, B8: 8);
+tuple_buckets!(B0: 0, B1: 1, B2: 2, B3: 3, B4: 4, B5: 5, B6: 6, B7: 7, B8: 8, B9: 9);
+tuple_buckets!(B0: 0, B1: 1, B2: 2, B3: 3, B4: 4, B5: 5, B6: 6, B7: 7, B8: 8, B9: 9, B10: 10);
+tuple_buckets!(B0: 0, B1: 1, B2: 2, B3: 3, B4: 4, B5: 5, B6: 6, B7: 7, B8: 8, B9: 9, B10: 10, B11: 11);
+
+/// A bucket allocator over a tuple of buckets, without the need for the `bucket_allocator` macro.
+///
+/// Buckets are checked in order of the tuple. Full buckets are skipped.
+pub struct BucketAllocator<B: Buckets>(UnsafeCell<B>);
+
+impl<B: Buckets> BucketAllocator<B> {
+    pub const fn new(buckets: B) -> Self {
+        BucketAllocator(UnsafeCell::new(buckets))
+    }
+}
+
+impl<B: Buckets + Default> Default for BucketAllocator<B> {
+    fn default() -> Self {
+        Self::new(B::default())
+    }
+}
+
+impl<B: Buckets> Debug for BucketAllocator<B> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("BucketAllocator").finish_non_exhaustive()
+    }
+}
+
+unsafe impl<B: Buckets> Sync for BucketAllocator<B> {}
+
+unsafe impl<B: Buckets> GlobalAlloc for BucketAllocator<B> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        (*self.0.get()).alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        (*self.0.get()).dealloc(ptr, layout);
+    }
+}
+
+/// Read-only access to a bucket of an allocator generated by `#[bucket_allocator]`, as returned by the method named after the bucket.
+pub struct BucketView<'a, B>(&'a UnsafeCell<B>);
+
+impl<'a, B> BucketView<'a, B> {
+    /// Used by `#[bucket_allocator]`.
+    ///
+    /// # Safety
+    /// The bucket must not be mutated while the view is used, other than by the allocator that owns it.
+    #[doc(hidden)]
+    pub const unsafe fn new(bucket: &'a UnsafeCell<B>) -> Self {
+        BucketView(bucket)
+    }
+
+    fn bucket(&self) -> &B {
+        unsafe { &*self.0.get() }
+    }
+}
+
+impl<S: Slot, const N: usize, Q: QuarantineQueue> BucketView<'_, BucketImpl<S, N, Q>> {
+    /// Whether `ptr` points into one of the bucket’s slots.
+    pub fn contains(&self, ptr: *const u8) -> bool {
+        self.bucket().slot_idx_for_ptr(ptr).is_some()
+    }
+
+    /// Number of slots in use. Quarantined slots are not in use.
+    pub fn num_live_slots(&self) -> usize {
+        let mut num = 0;
+        self.bucket().for_each_live_slot(|_| num += 1);
+        num
+    }
+
+    /// Total number of slots.
+    pub fn capacity(&self) -> usize {
+        N * NUM_SLOTS_PER_SEGMENT
+    }
+}
+
+impl<S: Slot, const N: usize> BucketView<'_, ArenaBucketImpl<S, N>> {
+    /// Whether `ptr` points into one of the bucket’s slots.
+    pub fn contains(&self, ptr: *const u8) -> bool {
+        self.bucket().slot_idx_for_ptr(ptr).is_some()
+    }
+
+    /// Number of slots in use.
+    pub fn num_live_slots(&self) -> usize {
+        let mut num = 0;
+        self.bucket().for_each_live_slot(|_| num += 1);
+        num
+    }
+
+    /// Number of segments that have been carved from the arena so far.
+    pub fn num_segments(&self) -> usize {
+        self.bucket().num_segments()
+    }
+
+    /// Number of slots the bucket can grow to.
+    pub fn capacity(&self) -> usize {
+        N * NUM_SLOTS_PER_SEGMENT
+    }
+}
+
+/// Usage statistics of a single bucket.
+///
+/// These are tracked by allocators generated with `#[bucket_allocator(stats = true)]` and returned by their `stats()` method.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BucketStats {
+    /// Name of the field the bucket was declared with.
+    pub name: &'static str,
+    pub slot_size: usize,
+    pub align: usize,
+    /// Total number of slots in the bucket.
+    pub capacity: usize,
+    /// Number of slots that are currently in use.
+    pub live_slots: usize,
+    /// Highest number of slots that have been in use at the same time.
+    pub peak_live_slots: usize,
+    /// Number of allocations served by this bucket.
+    pub allocations: usize,
+    /// Number of allocations returned to this bucket.
+    pub frees: usize,
+    /// Number of allocations that fit this bucket, but had to move on to a later bucket because this one was full.
+    pub fall_throughs: usize,
+    /// Number of allocations that failed, where this was the first bucket that could have served the allocation.
+    pub failures: usize,
+    /// Number of bytes in live slots that are not used by the allocations occupying them.
+    pub wasted_bytes: usize,
+}
+
+impl BucketStats {
+    pub const fn new(name: &'static str, slot_size: usize, align: usize, capacity: usize) -> Self {
+        BucketStats {
+            name,
+            slot_size,
+            align,
+            capacity,
+            live_slots: 0,
+            peak_live_slots: 0,
+            allocations: 0,
+            frees: 0,
+            fall_throughs: 0,
+            failures: 0,
+            wasted_bytes: 0,
+        }
+    }
+
+    /// Records an allocation of `size` bytes that occupies `num_slots` slots.
+    pub fn record_alloc(&mut self, size: usize, num_slots: usize) {
+        self.allocations += 1;
+        self.live_slots += num_slots;
+        self.peak_live_slots = self.peak_live_slots.max(self.live_slots);
+        self.wasted_bytes += (num_slots * self.slot_size).saturating_sub(size);
+    }
+
+    /// Records that an allocation of `size` bytes that occupied `num_slots` slots has been freed.
+    pub fn record_free(&mut self, size: usize, num_slots: usize) {
+        self.frees += 1;
+        self.live_slots = self.live_slots.saturating_sub(num_slots);
+        self.wasted_bytes = self
+            .wasted_bytes
+            .saturating_sub((num_slots * self.slot_size).saturating_sub(size));
+    }
+
+    /// Records that all slots of the bucket have been freed at once.
+    pub fn record_reset(&mut self) {
+        self.live_slots = 0;
+        self.wasted_bytes = 0;
+    }
+
+    pub fn record_fall_through(&mut self) {
+        self.fall_throughs += 1;
+    }
+
+    pub fn record_failure(&mut self) {
+        self.failures += 1;
+    }
+}
+
+pub struct SlotSize<const N: usize>;
+pub struct NumSlots<const N: usize>;
+pub struct Align<const N: usize>;
+pub struct MaxSpan<const N: usize>;
+
+pub struct Quarantine<const N: usize>;
+
+pub struct Bucket<S, N, A = Align<1>, M = MaxSpan<1>, Q = Quarantine<0>>(
+    PhantomData<S>,
+    PhantomData<N>,
+    PhantomData<A>,
+    PhantomData<M>,
+    PhantomData<Q>,
+);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use core::alloc::{GlobalAlloc, Layout};
+
+    use anyhow::Result;
+
+    use silly_alloc_macros::bucket_allocator;
+
+    #[bucket_allocator]
+    struct MyBucketAllocator {
+        vec2: Bucket<SlotSize<2>, NumSlots<32>, Align<2>>,
+        vec4: Bucket<SlotSize<4>, NumSlots<32>, Align<4>>,
+        vec8: Bucket<SlotSize<8>, NumSlots<32>, Align<8>>,
+    }
+
+    #[test]
+    fn next_in_bucket() -> Result<()> {
+        let b = MyBucketAllocator::new();
+        unsafe {
+            let ptr1 = b.alloc(Layout::from_size_align(2, 1)?);
+            let ptr2 = b.alloc(Layout::from_size_align(2, 1)?);
+            assert!(!ptr1.is_null());
+            assert!(!ptr2.is_null());
+            assert_eq!(ptr1.offset(2), ptr2);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn const_init() {
+        // The bucket is usable straight out of a const context, without any runtime initialization.
+        const BUCKET: BucketImpl<SlotWithAlign4<4>, 2> = BucketImpl::new();
+        let mut b = BUCKET;
+        let ptr1 = b.claim_first_available_slot().unwrap();
+        let ptr2 = b.claim_first_available_slot().unwrap();
+        assert_eq!(b.slot_idx_for_ptr(ptr1), Some(0));
+        assert_eq!(b.slot_idx_for_ptr(ptr2), Some(1));
+    }
+
+    // The `poison` feature adds a field to every bucket.
+    #[cfg(not(feature = "poison"))]
+    #[test]
+    fn dense_layout() {
+        // Headers are not padded to the slot alignment, so only the header array as a whole is padded once.
+        assert_eq!(
+            size_of::<BucketImpl<SlotWithAlign64<64>, 2>>(),
+            2 * NUM_SLOTS_PER_SEGMENT * 64 + 64
+        );
+        assert_eq!(
+            size_of::<BucketImpl<SlotWithAlign512<512>, 4>>(),
+            4 * NUM_SLOTS_PER_SEGMENT * 512 + 512
+        );
+        assert_eq!(
+            size_of::<BucketImpl<SlotWithAlign2<2>, 4>>(),
+            4 * NUM_SLOTS_PER_SEGMENT * 2 + 4 * SEGMENT_HEADER_SIZE
+        );
+    }
+
+    #[test]
+    fn slots_across_segments() {
+        let mut b = BucketImpl::<SlotWithAlign4<4>, 2>::new();
+        let ptrs: [*const u8; 64] =
+            core::array::from_fn(|_| b.claim_first_available_slot().unwrap());
+        assert!(b.claim_first_available_slot().is_none());
+        for (idx, &ptr) in ptrs.iter().enumerate() {
+            assert_eq!(b.slot_idx_for_ptr(ptr), Some(idx));
+        }
+        // Slots are densely packed, even across segment boundaries.
+        assert_eq!(unsafe { ptrs[31].offset(4) }, ptrs[32]);
+        assert_eq!(b.slot_idx_for_ptr(unsafe { ptrs[63].offset(4) }), None);
+        b.unset_slot(40);
+        assert_eq!(b.claim_first_available_slot(), Some(ptrs[40]));
+    }
+
+    #[test]
+    fn spans_across_segments() {
+        let mut b = BucketImpl::<SlotWithAlign4<4>, 2>::new();
+        let first = b.claim_span(30).unwrap();
+        assert_eq!(b.slot_idx_for_ptr(first), Some(0));
+        // Only 2 slots are left in the first segment, so the span continues into the second one.
+        let second = b.claim_span(4).unwrap();
+        assert_eq!(b.slot_idx_for_ptr(second), Some(30));
+        assert_eq!(b.claim_first_available_slot(), Some(b.get_slot(34)));
+        // No 30 consecutive slots are left.
+        assert!(b.claim_span(30).is_none());
+        b.unset_span(0, 30);
+        assert_eq!(b.claim_span(30), Some(first));
+    }
+
+    #[test]
+    fn full_bucket() -> Result<()> {
+        #[bucket_allocator]
+        struct MyBucketAllocator {
+            vec2: Bucket<SlotSize<2>, NumSlots<32>, Align<2>>,
+        }
+        unsafe {
+            let b = MyBucketAllocator::new();
+            let l = Layout::from_size_align(2, 1)?;
+            for _ in 0..32 {
+                b.alloc(l);
+            }
+            let ptr = b.alloc(l);
+            assert!(ptr.is_null());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn reuse() -> Result<()> {
+        let b = MyBucketAllocator::new();
+        unsafe {
+            let layout = Layout::from_size_align(2, 1)?;
+            let ptr1 = b.alloc(layout);
+            let ptr2 = b.alloc(layout);
+            let ptr3 = b.alloc(layout);
+            assert_eq!(ptr1.offset(2), ptr2);
+            assert_eq!(ptr2.offset(2), ptr3);
+            b.dealloc(ptr2, layout);
+            let ptr4 = b.alloc(layout);
+            assert_eq!(ptr2, ptr4);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn bucket_overflow() -> Result<()> {
+        let b = MyBucketAllocator::new();
+        unsafe {
+            let layout = Layout::from_size_align(2, 1)?;
+            // Fill 2 byte bucket
+            for _ in 0..32 {
+                b.alloc(layout);
+            }
+            let ptr1 = b.alloc(Layout::from_size_align(4, 1)?);
+            let ptr2 = b.alloc(layout);
+            assert_eq!(ptr1.offset(4), ptr2);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn alignment() -> Result<()> {
+        let b = MyBucketAllocator::new();
+        unsafe {
+            let layout = Layout::from_size_align(2, 8)?;
+            let ptr1 = b.alloc(layout);
+            // Alignment requirement should force the allocation into the last bucket desipte its size
+            assert!(b.vec8().contains(ptr1));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn alignment_fail() -> Result<()> {
+        let b = MyBucketAllocator::new();
+        unsafe {
+            let layout = Layout::from_size_align(2, 32)?;
+            let ptr1 = b.alloc(layout);
+            assert!(ptr1.is_null());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn aligned_slots() {
+        let mut b = BucketImpl::<SlotWithAlign4<4>, 1>::new();
+        let ptr1 = b.claim_first_aligned_slot(8).unwrap();
+        let ptr2 = b.claim_first_aligned_slot(8).unwrap();
+        assert_eq!(ptr1 as usize % 8, 0);
+        // Every other slot is 8-aligned.
+        assert_eq!(unsafe { ptr1.offset(8) }, ptr2);
+        // The slot between them is still free.
+        let ptr3 = b.claim_first_available_slot().unwrap();
+        assert!(ptr3 < ptr2 && ptr3 != ptr1);
+    }
+
+    #[test]
+    fn aligned_slot_search() -> Result<()> {
+        #[bucket_allocator(aligned_slot_search = true)]
+        struct MyBucketAllocator {
+            vec4: Bucket<SlotSize<4>, NumSlots<32>, Align<4>>,
+            vec16: Bucket<SlotSize<16>, NumSlots<32>, Align<16>>,
+        }
+
+        unsafe {
+            let b = MyBucketAllocator::new();
+            let l = Layout::from_size_align(4, 8)?;
+            for _ in 0..16 {
+                let ptr = b.alloc(l);
+                assert_eq!(ptr as usize % 8, 0);
+                assert!(b.vec4().contains(ptr));
+            }
+            // All 8-aligned slots are taken, so the allocation moves on to the next bucket.
+            let ptr = b.alloc(l);
+            assert!(b.vec16().contains(ptr));
+            // The remaining slots can still be used for allocations with a smaller alignment.
+            let ptr = b.alloc(Layout::from_size_align(4, 4)?);
+            assert!(b.vec4().contains(ptr));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn spans() -> Result<()> {
+        #[bucket_allocator(stats = true)]
+        struct MyBucketAllocator {
+            vec32: Bucket<SlotSize<32>, NumSlots<32>, Align<8>, MaxSpan<4>>,
+        }
+
+        unsafe {
+            let b = MyBucketAllocator::new();
+            let small = Layout::from_size_align(8, 8)?;
+            let big = Layout::from_size_align(100, 8)?;
+            let ptr1 = b.alloc(small);
+            let ptr2 = b.alloc(big);
+            let ptr3 = b.alloc(small);
+            assert_eq!(ptr2, ptr1.add(32));
+            // The 100 byte allocation occupies 4 slots.
+            assert_eq!(ptr3, ptr2.add(4 * 32));
+            // Too big, even for a span.
+            assert!(b.alloc(Layout::from_size_align(129, 8)?).is_null());
+
+            let [stats] = b.stats();
+            assert_eq!(stats.live_slots, 6);
+            assert_eq!(stats.wasted_bytes, 2 * 24 + 28);
+
+            // The number of slots to free is derived from the layout.
+            b.dealloc(ptr2, big);
+            assert_eq!(b.stats()[0].live_slots, 2);
+            assert_eq!(b.alloc(big), ptr2);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn page_alignment() -> Result<()> {
+        #[bucket_allocator]
+        struct MyBucketAllocator {
+            page: Bucket<SlotSize<4096>, NumSlots<2>, Align<4096>>,
+            wasm_page: Bucket<SlotSize<65536>, NumSlots<1>, Align<65536>>,
+        }
+        static B: MyBucketAllocator = MyBucketAllocator::new();
+
+        unsafe {
+            let ptr1 = B.alloc(Layout::from_size_align(4096, 4096)?);
+            let ptr2 = B.alloc(Layout::from_size_align(100, 4096)?);
+            assert_eq!(ptr1 as usize % 4096, 0);
+            assert_eq!(ptr2, ptr1.add(4096));
+            let ptr3 = B.alloc(Layout::from_size_align(8, 65536)?);
+            assert_eq!(ptr3 as usize % 65536, 0);
         }
         Ok(())
     }
@@ -447,7 +1461,7 @@ mod test {
         #[bucket_allocator(sort_buckets = true)]
         struct MyBucketAllocator {
             vec8: Bucket<SlotSize<8>, NumSlots<32>, Align<8>>,
-            vec2: Bucket<SlotSize<2>, NumSlots<32>, Align<8>>,
+            vec2: Bucket<SlotSize<2>, NumSlots<32>, Align<2>>,
         }
 
         unsafe {
@@ -459,4 +1473,606 @@ mod test {
         }
         Ok(())
     }
+
+    /// Fills all slots of `bucket` with allocations of `layout` from `b`.
+    unsafe fn fill_bucket<A: GlobalAlloc, S: Slot, const N: usize>(
+        b: &A,
+        layout: Layout,
+        bucket: BucketView<'_, BucketImpl<S, N>>,
+    ) {
+        for _ in 0..bucket.capacity() {
+            let ptr = b.alloc(layout);
+            assert!(bucket.contains(ptr));
+        }
+        assert_eq!(bucket.num_live_slots(), bucket.capacity());
+    }
+
+    #[test]
+    fn first_fit_selection() -> Result<()> {
+        #[bucket_allocator(selection = first_fit)]
+        struct Spilling {
+            vec8: Bucket<SlotSize<8>, NumSlots<32>, Align<8>>,
+            vec16: Bucket<SlotSize<16>, NumSlots<32>, Align<8>>,
+        }
+        #[bucket_allocator(selection = first_fit, spill = false)]
+        struct NotSpilling {
+            vec8: Bucket<SlotSize<8>, NumSlots<32>, Align<8>>,
+            vec16: Bucket<SlotSize<16>, NumSlots<32>, Align<8>>,
+        }
+
+        unsafe {
+            let l = Layout::from_size_align(4, 4)?;
+            let b = Spilling::new();
+            fill_bucket(&b, l, b.vec8());
+            fill_bucket(&b, l, b.vec16());
+            assert!(b.alloc(l).is_null());
+
+            let b = NotSpilling::new();
+            fill_bucket(&b, l, b.vec8());
+            assert!(b.alloc(l).is_null());
+            // Allocations that don’t fit the full bucket are unaffected.
+            fill_bucket(&b, Layout::from_size_align(16, 8)?, b.vec16());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn best_fit_selection() -> Result<()> {
+        #[bucket_allocator(selection = best_fit)]
+        struct Spilling {
+            vec16: Bucket<SlotSize<16>, NumSlots<32>, Align<8>>,
+            vec8: Bucket<SlotSize<8>, NumSlots<32>, Align<8>>,
+        }
+        #[bucket_allocator(selection = best_fit, spill = false)]
+        struct NotSpilling {
+            vec16: Bucket<SlotSize<16>, NumSlots<32>, Align<8>>,
+            vec8: Bucket<SlotSize<8>, NumSlots<32>, Align<8>>,
+        }
+
+        unsafe {
+            let l = Layout::from_size_align(4, 4)?;
+            let b = Spilling::new();
+            // The smallest bucket is picked despite being declared last.
+            fill_bucket(&b, l, b.vec8());
+            fill_bucket(&b, l, b.vec16());
+            assert!(b.alloc(l).is_null());
+
+            let b = NotSpilling::new();
+            fill_bucket(&b, l, b.vec8());
+            assert!(b.alloc(l).is_null());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn least_waste_selection() -> Result<()> {
+        #[bucket_allocator(selection = least_waste)]
+        struct Spilling {
+            aligned: Bucket<SlotSize<8>, NumSlots<32>, Align<8>>,
+            unaligned: Bucket<SlotSize<8>, NumSlots<32>, Align<2>>,
+        }
+        #[bucket_allocator(selection = least_waste, spill = false)]
+        struct NotSpilling {
+            aligned: Bucket<SlotSize<8>, NumSlots<32>, Align<8>>,
+            unaligned: Bucket<SlotSize<8>, NumSlots<32>, Align<2>>,
+        }
+
+        unsafe {
+            let l = Layout::from_size_align(8, 1)?;
+            let b = Spilling::new();
+            // Among buckets of the same size, the smallest alignment is picked.
+            fill_bucket(&b, l, b.unaligned());
+            fill_bucket(&b, l, b.aligned());
+            assert!(b.alloc(l).is_null());
+
+            let b = NotSpilling::new();
+            fill_bucket(&b, l, b.unaligned());
+            assert!(b.alloc(l).is_null());
+            // Aligned slots are still available for the allocations that need them.
+            fill_bucket(&b, Layout::from_size_align(8, 8)?, b.aligned());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn selection_orders() {
+        assert_eq!(
+            selection_order([(16, 8), (8, 8), (8, 2), (4, 4)], Selection::BestFit),
+            [3, 1, 2, 0]
+        );
+        assert_eq!(
+            selection_order([(16, 8), (8, 8), (8, 2), (4, 4)], Selection::LeastWaste),
+            [3, 2, 1, 0]
+        );
+        assert_eq!(
+            selection_order([(16, 8), (8, 8)], Selection::FirstFit),
+            [0, 1]
+        );
+    }
+
+    #[test]
+    fn generic_allocator() -> Result<()> {
+        let b = BucketAllocator::new((
+            BucketImpl::<SlotWithAlign2<2>, 1>::new(),
+            BucketImpl::<SlotWithAlign8<8>, 1>::new(),
+        ));
+        unsafe {
+            let l = Layout::from_size_align(2, 1)?;
+            let ptr1 = b.alloc(l);
+            let ptr2 = b.alloc(l);
+            assert_eq!(ptr1.offset(2), ptr2);
+            b.dealloc(ptr1, l);
+            assert_eq!(b.alloc(l), ptr1);
+            // Alignment requirement forces the allocation into the second bucket.
+            let ptr3 = b.alloc(Layout::from_size_align(2, 8)?);
+            assert_eq!(ptr3 as usize % 8, 0);
+            assert!(ptr3 >= &(*b.0.get()).1 as *const _ as *mut u8);
+            assert!(b.alloc(Layout::from_size_align(16, 1)?).is_null());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn generic_allocator_overflow() -> Result<()> {
+        static ALLOCATOR: BucketAllocator<(
+            BucketImpl<SlotWithAlign4<4>, 1>,
+            BucketImpl<SlotWithAlign8<8>, 1>,
+        )> = BucketAllocator::new((BucketImpl::new(), BucketImpl::new()));
+        unsafe {
+            let l = Layout::from_size_align(4, 4)?;
+            for _ in 0..64 {
+                assert!(!ALLOCATOR.alloc(l).is_null());
+            }
+            assert!(ALLOCATOR.alloc(l).is_null());
+        }
+        Ok(())
+    }
+
+    mod exported {
+        use silly_alloc_macros::bucket_allocator;
+
+        /// An allocator that is visible outside of its module.
+        #[bucket_allocator]
+        #[cfg_attr(test, repr(C))]
+        #[derive(Debug)]
+        pub struct ExportedBucketAllocator {
+            /// Bucket for small things.
+            pub small: Bucket<SlotSize<4>, NumSlots<32>, Align<4>>,
+        }
+    }
+
+    #[test]
+    fn visibility_and_attributes() -> Result<()> {
+        use exported::ExportedBucketAllocator;
+
+        static ALLOCATOR: ExportedBucketAllocator = ExportedBucketAllocator::new();
+        unsafe {
+            let ptr = ALLOCATOR.alloc(Layout::from_size_align(4, 4)?);
+            // Bucket accessors are as visible as the struct.
+            assert!(ALLOCATOR.small().contains(ptr));
+            assert_eq!(ALLOCATOR.small().num_live_slots(), 1);
+            let mut live = 0;
+            ALLOCATOR.for_each_live(|name, slot, _| {
+                assert_eq!((name, slot), ("small", ptr as *const u8));
+                live += 1;
+            });
+            assert_eq!(live, 1);
+        }
+        // Attributes of the original struct are carried over, so the first bucket is at the start of the struct.
+        let b = ExportedBucketAllocator::new();
+        unsafe {
+            let ptr = b.alloc(Layout::from_size_align(4, 4)?);
+            assert_eq!(ptr as *const u8, &b as *const _ as *const u8);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn const_expr_params() -> Result<()> {
+        #[allow(dead_code)]
+        #[repr(align(16))]
+        struct Entity([u32; 6]);
+        const MAX_ENTITIES: usize = 40;
+
+        #[bucket_allocator(stats = true)]
+        struct MyBucketAllocator {
+            entities: Bucket<
+                SlotSize<{ core::mem::size_of::<Entity>() }>,
+                NumSlots<MAX_ENTITIES>,
+                Align<{ core::mem::align_of::<Entity>() }>,
+            >,
+            big: Bucket<SlotSize<{ 4 * 1024 }>, NumSlots<{ 2 * 16 }>, Align<{ 1 << 3 }>>,
+        }
+
+        let b = MyBucketAllocator::new();
+        let [entities, big] = b.stats();
+        assert_eq!(entities.slot_size, 32);
+        assert_eq!(entities.align, 16);
+        assert_eq!(entities.capacity, 64);
+        assert_eq!(big.slot_size, 4096);
+        assert_eq!(big.align, 8);
+        assert_eq!(big.capacity, 32);
+        unsafe {
+            let ptr = b.alloc(Layout::new::<Entity>());
+            assert_eq!(ptr, b.entities().0.get() as *mut u8);
+            assert!(!b.alloc(Layout::from_size_align(4000, 8)?).is_null());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn stats() -> Result<()> {
+        #[bucket_allocator(stats = true)]
+        struct MyBucketAllocator {
+            vec2: Bucket<SlotSize<2>, NumSlots<32>, Align<2>>,
+            vec8: Bucket<SlotSize<8>, NumSlots<32>, Align<8>>,
+        }
+
+        unsafe {
+            let b = MyBucketAllocator::new();
+            let l1 = Layout::from_size_align(1, 1)?;
+            let ptrs: [*mut u8; 33] = core::array::from_fn(|_| b.alloc(l1));
+            let l2 = Layout::from_size_align(5, 8)?;
+            let ptr = b.alloc(l2);
+            b.dealloc(ptrs[0], l1);
+            b.dealloc(ptrs[32], l1);
+            for _ in 0..32 {
+                b.alloc(l2);
+            }
+
+            let [vec2, vec8] = b.stats();
+            assert_eq!(vec2.name, "vec2");
+            assert_eq!(vec2.slot_size, 2);
+            assert_eq!(vec2.align, 2);
+            assert_eq!(vec2.capacity, 32);
+            assert_eq!(vec2.allocations, 32);
+            assert_eq!(vec2.frees, 1);
+            assert_eq!(vec2.live_slots, 31);
+            assert_eq!(vec2.peak_live_slots, 32);
+            assert_eq!(vec2.fall_throughs, 1);
+            assert_eq!(vec2.failures, 0);
+            assert_eq!(vec2.wasted_bytes, 31);
+
+            assert_eq!(vec8.name, "vec8");
+            assert_eq!(vec8.allocations, 33);
+            assert_eq!(vec8.frees, 1);
+            assert_eq!(vec8.live_slots, 32);
+            assert_eq!(vec8.peak_live_slots, 32);
+            assert_eq!(vec8.fall_throughs, 1);
+            assert_eq!(vec8.failures, 1);
+            assert_eq!(vec8.wasted_bytes, 32 * 3);
+
+            b.dealloc(ptr, l2);
+            assert_eq!(b.stats()[1].live_slots, 31);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn stats_without_spilling() -> Result<()> {
+        #[bucket_allocator(stats = true, spill = false)]
+        struct MyBucketAllocator {
+            vec2: Bucket<SlotSize<2>, NumSlots<32>, Align<2>>,
+            vec8: Bucket<SlotSize<8>, NumSlots<32>, Align<8>>,
+        }
+
+        unsafe {
+            let b = MyBucketAllocator::new();
+            let l1 = Layout::from_size_align(1, 1)?;
+            for _ in 0..32 {
+                assert!(!b.alloc(l1).is_null());
+            }
+            assert!(b.alloc(l1).is_null());
+
+            let [vec2, vec8] = b.stats();
+            assert_eq!(vec2.allocations, 32);
+            assert_eq!(vec2.fall_throughs, 0);
+            assert_eq!(vec2.failures, 1);
+            assert_eq!(vec8.allocations, 0);
+            assert_eq!(vec8.failures, 0);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn reset() -> Result<()> {
+        #[bucket_allocator(stats = true)]
+        struct MyBucketAllocator {
+            vec2: Bucket<SlotSize<2>, NumSlots<64>, Align<2>>,
+            vec8: Bucket<SlotSize<8>, NumSlots<32>, Align<8>>,
+        }
+
+        unsafe {
+            let b = MyBucketAllocator::new();
+            let l2 = Layout::from_size_align(2, 2)?;
+            let l8 = Layout::from_size_align(8, 8)?;
+            let first2 = b.alloc(l2);
+            let first8 = b.alloc(l8);
+            for _ in 0..40 {
+                b.alloc(l2);
+                b.alloc(l8);
+            }
+
+            assert!(b.reset_bucket("vec2"));
+            assert!(!b.reset_bucket("vec4"));
+            let [vec2, vec8] = b.stats();
+            assert_eq!(vec2.live_slots, 0);
+            assert_eq!(vec8.live_slots, 32);
+            assert_eq!(b.alloc(l2), first2);
+            assert!(b.alloc(l8).is_null());
+
+            b.reset();
+            assert!(b.stats().iter().all(|stats| stats.live_slots == 0));
+            assert_eq!(b.alloc(l2), first2);
+            assert_eq!(b.alloc(l8), first8);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn for_each_live() -> Result<()> {
+        #[bucket_allocator]
+        struct MyBucketAllocator {
+            vec2: Bucket<SlotSize<2>, NumSlots<64>, Align<2>>,
+            vec8: Bucket<SlotSize<8>, NumSlots<32>, Align<8>>,
+        }
+
+        unsafe {
+            let b = MyBucketAllocator::new();
+            let l2 = Layout::from_size_align(2, 2)?;
+            let ptrs: [*mut u8; 40] = core::array::from_fn(|_| b.alloc(l2));
+            let ptr8 = b.alloc(Layout::from_size_align(8, 8)?);
+            for &ptr in ptrs.iter().step_by(2) {
+                b.dealloc(ptr, l2);
+            }
+
+            let mut live = [("", core::ptr::null(), 0); 21];
+            let mut num_live = 0;
+            b.for_each_live(|name, ptr, slot_size| {
+                live[num_live] = (name, ptr, slot_size);
+                num_live += 1;
+            });
+            assert_eq!(num_live, 21);
+            for (idx, &ptr) in ptrs.iter().skip(1).step_by(2).enumerate() {
+                assert_eq!(live[idx], ("vec2", ptr as *const u8, 2));
+            }
+            assert_eq!(live[20], ("vec8", ptr8 as *const u8, 8));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn quarantine() -> Result<()> {
+        #[bucket_allocator]
+        struct MyBucketAllocator {
+            vec8: Bucket<SlotSize<8>, NumSlots<32>, Align<8>, MaxSpan<2>, Quarantine<2>>,
+        }
+
+        unsafe {
+            let b = MyBucketAllocator::new();
+            let l8 = Layout::from_size_align(8, 8)?;
+            let l16 = Layout::from_size_align(16, 8)?;
+            let ptr1 = b.alloc(l16);
+            let ptr2 = b.alloc(l8);
+            b.dealloc(ptr1, l16);
+            b.dealloc(ptr2, l8);
+            let mut num_live = 0;
+            b.for_each_live(|_, _, _| num_live += 1);
+            assert_eq!(num_live, 0);
+
+            // Both freed allocations are still quarantined.
+            let ptr3 = b.alloc(l8);
+            assert_eq!(ptr3, ptr2.add(8));
+            // Releases the span of the first allocation.
+            b.dealloc(ptr3, l8);
+            assert_eq!(b.alloc(l16), ptr1);
+
+            (*b.vec8().0.get()).flush_quarantine();
+            assert_eq!(b.alloc(l8), ptr2);
+            assert_eq!(b.alloc(l8), ptr3);
+
+            b.reset();
+            assert_eq!(b.alloc(l8), ptr1);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "poison")]
+    #[test]
+    fn poison_quarantine() {
+        use crate::poison::is_freed;
+
+        #[bucket_allocator]
+        struct MyBucketAllocator {
+            vec8: Bucket<SlotSize<8>, NumSlots<32>, Align<8>, Quarantine<1>>,
+        }
+
+        let b = MyBucketAllocator::new();
+        unsafe {
+            let l = Layout::from_size_align(8, 8).unwrap();
+            let ptr = b.alloc(l);
+            b.dealloc(ptr, l);
+            ptr.write(42);
+            // The slot is quarantined, so the write doesn’t hit the next allocation…
+            let other = b.alloc(l);
+            assert_ne!(other, ptr);
+            // …but it is released with the write still visible, so claiming it again would abort.
+            b.dealloc(other, l);
+            assert!(!is_freed(ptr, 8));
+        }
+    }
+
+    #[cfg(feature = "poison")]
+    #[test]
+    fn poison() -> Result<()> {
+        use crate::poison::{FREED, FRESH};
+
+        let b = MyBucketAllocator::new();
+        unsafe {
+            let l = Layout::from_size_align(8, 8)?;
+            let ptr = b.alloc(l);
+            assert!(core::slice::from_raw_parts(ptr, 8)
+                .iter()
+                .all(|&b| b == FRESH));
+            b.dealloc(ptr, l);
+            assert!(core::slice::from_raw_parts(ptr, 8)
+                .iter()
+                .all(|&b| b == FREED));
+            assert_eq!(b.alloc(l), ptr);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "poison")]
+    #[test]
+    fn poison_use_after_free() {
+        use crate::poison::{is_freed, FREED};
+
+        let mut b = BucketImpl::<SlotWithAlign8<8>, 1>::new();
+        let ptr = b.claim_first_available_slot().unwrap();
+        b.unset_slot(0);
+        unsafe {
+            assert!(is_freed(ptr, 8));
+            // Writing zeroes is caught as well, as never claimed slots don’t pass as freed.
+            (ptr as *mut u8).write_bytes(0, 8);
+            assert!(!is_freed(ptr, 8));
+            (ptr as *mut u8).write_bytes(FREED, 8);
+        }
+        // Never claimed slots are poisoned right before their first use, so claiming them passes the check.
+        assert_eq!(b.claim_span(3), Some(ptr));
+        unsafe {
+            assert!(core::slice::from_raw_parts(b.get_slot(3), 8)
+                .iter()
+                .all(|&b| b == 0));
+        }
+    }
+
+    #[test]
+    fn geometric_size_classes() -> Result<()> {
+        #[bucket_allocator(
+            stats = true,
+            size_classes = geometric(min = 8, max = 128, ratio = 1.25),
+            bytes_per_class = 1024
+        )]
+        struct MyBucketAllocator {}
+
+        let b = MyBucketAllocator::new();
+        let stats = b.stats();
+        assert_eq!(
+            stats.map(|s| s.slot_size),
+            [8, 16, 24, 32, 40, 56, 72, 96, 120, 128]
+        );
+        assert_eq!(stats.map(|s| s.align), [8, 16, 8, 32, 8, 8, 8, 32, 8, 128]);
+        assert_eq!(stats[0].name, "size_class_8");
+        // 1024 / 8 = 128 slots
+        assert_eq!(stats[0].capacity, 128);
+        // 1024 / 128 = 8 slots, rounded up to a full segment
+        assert_eq!(stats[9].capacity, 32);
+
+        unsafe {
+            let ptr = b.alloc(Layout::from_size_align(100, 8)?);
+            assert!(!ptr.is_null());
+        }
+        assert_eq!(b.stats()[8].allocations, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn mixed_size_classes() -> Result<()> {
+        #[bucket_allocator(
+            stats = true,
+            size_classes = powers_of_two(min = 16, max = 4096),
+            bytes_per_class = 4096
+        )]
+        struct MyBucketAllocator {
+            tiny: Bucket<SlotSize<4>, NumSlots<32>, Align<4>>,
+        }
+
+        let b = MyBucketAllocator::new();
+        let stats = b.stats();
+        assert_eq!(
+            stats.map(|s| s.name),
+            [
+                "tiny",
+                "size_class_16",
+                "size_class_32",
+                "size_class_64",
+                "size_class_128",
+                "size_class_256",
+                "size_class_512",
+                "size_class_1024",
+                "size_class_2048",
+                "size_class_4096"
+            ]
+        );
+        // Alignment is capped at 512.
+        assert_eq!(stats[9].align, 512);
+        unsafe {
+            assert!(!b.alloc(Layout::from_size_align(3, 1)?).is_null());
+            assert!(!b.alloc(Layout::from_size_align(3000, 512)?).is_null());
+        }
+        assert_eq!(b.stats()[0].allocations, 1);
+        assert_eq!(b.stats()[9].allocations, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn arena_buckets() -> Result<()> {
+        #[bucket_allocator(arena = &'static [u8])]
+        struct MyBucketAllocator {
+            vec2: Bucket<SlotSize<2>, NumSlots<64>, Align<2>>,
+            vec8: Bucket<SlotSize<8>, NumSlots<32>, Align<8>>,
+        }
+
+        let arena: &'static [u8] = vec![0u8; 1024].leak();
+        unsafe {
+            let b = MyBucketAllocator::with_arena(arena);
+            // Nothing is carved from the arena until the first allocation.
+            assert_eq!(b.vec2().num_segments(), 0);
+            let l = Layout::from_size_align(2, 2)?;
+            let ptr1 = b.alloc(l);
+            assert!(arena.as_ptr_range().contains(&(ptr1 as *const u8)));
+            assert_eq!(b.vec2().num_segments(), 1);
+            for _ in 1..32 {
+                assert!(!b.alloc(l).is_null());
+            }
+            // The first segment is full, so the 33rd allocation has to grow the bucket.
+            let ptr2 = b.alloc(l);
+            assert!(!ptr2.is_null());
+            assert_eq!(b.vec2().num_segments(), 2);
+            b.dealloc(ptr2, l);
+            assert_eq!(b.alloc(l), ptr2);
+            b.dealloc(ptr1, l);
+            assert_eq!(b.alloc(l), ptr1);
+            // The bucket is capped at `NumSlots`, so further allocations spill into the next bucket.
+            for _ in 33..64 {
+                assert!(!b.alloc(l).is_null());
+            }
+            assert_eq!(b.vec8().num_segments(), 0);
+            assert!(!b.alloc(l).is_null());
+            assert_eq!(b.vec2().num_segments(), 2);
+            assert_eq!(b.vec8().num_segments(), 1);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn arena_exhausted() -> Result<()> {
+        #[bucket_allocator(arena = &'static [u8])]
+        struct MyBucketAllocator {
+            vec8: Bucket<SlotSize<8>, NumSlots<1024>, Align<8>>,
+        }
+
+        // Only enough room for a single segment.
+        let arena: &'static [u8] = vec![0u8; 300].leak();
+        unsafe {
+            let b = MyBucketAllocator::with_arena(arena);
+            let l = Layout::from_size_align(8, 8)?;
+            for _ in 0..32 {
+                assert!(!b.alloc(l).is_null());
+            }
+            assert!(b.alloc(l).is_null());
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,370 @@
+/*!
+Typed object pools.
+*/
+
+use core::{
+    cell::UnsafeCell,
+    fmt::{Debug, Formatter},
+    marker::PhantomData,
+    mem::{size_of, MaybeUninit},
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+};
+
+use super::{BucketImpl, Slot, Zeroable, NUM_SLOTS_PER_SEGMENT};
+
+/// A slot with the size and alignment of `T`.
+#[repr(transparent)]
+pub struct TypedSlot<T>(MaybeUninit<T>);
+
+unsafe impl<T> Zeroable for TypedSlot<T> {}
+
+impl<T> Slot for TypedSlot<T> {
+    fn get(&self) -> *const u8 {
+        self.0.as_ptr() as *const u8
+    }
+
+    fn size() -> usize {
+        size_of::<T>()
+    }
+}
+
+/// A pool of values of type `T`, backed by a [`BucketImpl`] with `N` segments of 32 slots each.
+///
+/// In contrast to the bucket allocators, a pool is not meant to be a global allocator. It is a local, fixed-size storage for many values of the same type, e.g. the entities of a game. The slot type is derived from `T`, so its size and alignment always match.
+///
+/// ```rust
+/// use silly_alloc::bucket::Pool;
+///
+/// struct Entity { id: u64, position: [f32; 3] }
+///
+/// let pool: Pool<Entity, 2> = Pool::new();
+/// let mut player = pool.alloc(Entity { id: 1, position: [0.0; 3] }).unwrap();
+/// player.position[0] += 1.0;
+/// // Returns the slot to the pool.
+/// drop(player);
+/// ```
+pub struct Pool<T, const N: usize> {
+    bucket: UnsafeCell<BucketImpl<TypedSlot<T>, N>>,
+}
+
+impl<T, const N: usize> Pool<T, N> {
+    /// Number of values the pool can hold at the same time.
+    pub const CAPACITY: usize = N * NUM_SLOTS_PER_SEGMENT;
+
+    /// Creates an empty pool.
+    ///
+    /// # Panics
+    /// Panics if `T` is a zero-sized type, as those can’t be told apart by their address.
+    pub const fn new() -> Self {
+        assert!(
+            size_of::<T>() != 0,
+            "Pool does not support zero-sized types"
+        );
+        Pool {
+            bucket: UnsafeCell::new(BucketImpl::new()),
+        }
+    }
+
+    /// Moves `value` into a free slot of the pool. Returns `None` if the pool is full.
+    pub fn alloc(&self, value: T) -> Option<PoolBox<'_, T>> {
+        let bucket = unsafe { &mut *self.bucket.get() };
+        let ptr = bucket.claim_first_available_slot()? as *mut T;
+        unsafe { ptr.write(value) };
+        Some(PoolBox {
+            ptr: unsafe { NonNull::new_unchecked(ptr) },
+            pool: self,
+            _value: PhantomData,
+        })
+    }
+}
+
+impl<T, const N: usize> Default for Pool<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Debug for Pool<T, N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Pool")
+            .field("bucket", unsafe { &*self.bucket.get() })
+            .finish()
+    }
+}
+
+/// Lets a [`PoolBox`] return its slot without knowing the number of segments of its pool.
+trait ReturnSlot {
+    /// # Safety
+    /// `ptr` must point to a claimed slot of the pool whose value has been dropped.
+    unsafe fn return_slot(&self, ptr: *const u8);
+}
+
+impl<T, const N: usize> ReturnSlot for Pool<T, N> {
+    unsafe fn return_slot(&self, ptr: *const u8) {
+        let bucket = &mut *self.bucket.get();
+        if let Some(slot_idx) = bucket.slot_idx_for_ptr(ptr) {
+            bucket.unset_slot(slot_idx);
+        }
+    }
+}
+
+/// A value in a [`Pool`]. Dropping the box drops the value and returns its slot to the pool.
+pub struct PoolBox<'a, T> {
+    ptr: NonNull<T>,
+    pool: &'a dyn ReturnSlot,
+    _value: PhantomData<T>,
+}
+
+impl<T> Deref for PoolBox<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> DerefMut for PoolBox<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T> Drop for PoolBox<'_, T> {
+    fn drop(&mut self) {
+        unsafe {
+            self.ptr.as_ptr().drop_in_place();
+            self.pool.return_slot(self.ptr.as_ptr() as *const u8);
+        }
+    }
+}
+
+impl<T: Debug> Debug for PoolBox<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+/// Refers to a value in a [`HandlePool`].
+///
+/// A handle stays valid until its value is removed. After that, it never refers to another value, even if its slot is reused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Handle {
+    index: usize,
+    generation: u32,
+}
+
+impl Handle {
+    /// Index of the slot the value is stored in.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Number of times the slot had been freed before the value was inserted.
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+/// A pool of values of type `T` that are referred to by [`Handle`]s instead of pointers, backed by a [`BucketImpl`] with `N` segments of 32 slots each.
+///
+/// Every slot has a generation counter that is incremented when its value is removed. A handle remembers the generation of its slot, so a stale handle is detected instead of silently referring to whatever value reuses the slot.
+///
+/// ```rust
+/// use silly_alloc::bucket::HandlePool;
+///
+/// let mut pool: HandlePool<&str, 1> = HandlePool::new();
+/// let handle = pool.insert("player").unwrap();
+/// assert_eq!(pool.get(handle), Some(&"player"));
+/// pool.remove(handle);
+/// let other = pool.insert("enemy").unwrap();
+/// assert_eq!(pool.get(handle), None);
+/// assert_eq!(pool.get(other), Some(&"enemy"));
+/// ```
+pub struct HandlePool<T, const N: usize> {
+    bucket: BucketImpl<TypedSlot<T>, N>,
+    generations: [[u32; NUM_SLOTS_PER_SEGMENT]; N],
+}
+
+impl<T, const N: usize> HandlePool<T, N> {
+    /// Number of values the pool can hold at the same time.
+    pub const CAPACITY: usize = N * NUM_SLOTS_PER_SEGMENT;
+
+    /// Creates an empty pool.
+    ///
+    /// # Panics
+    /// Panics if `T` is a zero-sized type, as those can’t be told apart by their address.
+    pub const fn new() -> Self {
+        assert!(
+            size_of::<T>() != 0,
+            "HandlePool does not support zero-sized types"
+        );
+        HandlePool {
+            bucket: BucketImpl::new(),
+            generations: [[0; NUM_SLOTS_PER_SEGMENT]; N],
+        }
+    }
+
+    fn generation_mut(&mut self, index: usize) -> &mut u32 {
+        &mut self.generations[index / NUM_SLOTS_PER_SEGMENT][index % NUM_SLOTS_PER_SEGMENT]
+    }
+
+    /// Returns a pointer to the value `handle` refers to, if it is still in the pool.
+    fn value_ptr(&self, handle: Handle) -> Option<*mut T> {
+        let generation = *self
+            .generations
+            .get(handle.index / NUM_SLOTS_PER_SEGMENT)?
+            .get(handle.index % NUM_SLOTS_PER_SEGMENT)?;
+        // Handles of another pool may point at a free slot of this one.
+        if generation != handle.generation || !self.bucket.is_slot_set(handle.index) {
+            return None;
+        }
+        Some(self.bucket.get_slot(handle.index) as *mut T)
+    }
+
+    /// Moves `value` into a free slot of the pool. Returns `None` if the pool is full.
+    pub fn insert(&mut self, value: T) -> Option<Handle> {
+        let ptr = self.bucket.claim_first_available_slot()?;
+        unsafe { (ptr as *mut T).write(value) };
+        let index = self.bucket.slot_idx_for_ptr(ptr)?;
+        Some(Handle {
+            index,
+            generation: *self.generation_mut(index),
+        })
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        self.value_ptr(handle).map(|ptr| unsafe { &*ptr })
+    }
+
+    pub fn get_mut(&mut self, handle: Handle) -> Option<&mut T> {
+        self.value_ptr(handle).map(|ptr| unsafe { &mut *ptr })
+    }
+
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.value_ptr(handle).is_some()
+    }
+
+    /// Moves the value out of the pool and frees its slot. Returns `None` if `handle` is stale.
+    pub fn remove(&mut self, handle: Handle) -> Option<T> {
+        let ptr = self.value_ptr(handle)?;
+        let value = unsafe { ptr.read() };
+        let generation = self.generation_mut(handle.index);
+        *generation = generation.wrapping_add(1);
+        self.bucket.unset_slot(handle.index);
+        Some(value)
+    }
+}
+
+impl<T, const N: usize> Default for HandlePool<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Debug for HandlePool<T, N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("HandlePool")
+            .field("bucket", &self.bucket)
+            .finish()
+    }
+}
+
+impl<T, const N: usize> Drop for HandlePool<T, N> {
+    fn drop(&mut self) {
+        self.bucket
+            .for_each_live_slot(|ptr| unsafe { (ptr as *mut T).drop_in_place() });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use core::cell::Cell;
+
+    #[test]
+    fn reuses_slots() {
+        let pool: Pool<u64, 1> = Pool::new();
+        let boxes: [PoolBox<u64>; 32] = core::array::from_fn(|i| pool.alloc(i as u64).unwrap());
+        assert!(pool.alloc(32).is_none());
+        for (i, b) in boxes.iter().enumerate() {
+            assert_eq!(**b, i as u64);
+            assert_eq!(&**b as *const u64 as usize % align_of::<u64>(), 0);
+        }
+
+        let [first, ..] = boxes;
+        let addr = &*first as *const u64;
+        drop(first);
+        let mut b = pool.alloc(100).unwrap();
+        assert_eq!(&*b as *const u64, addr);
+        *b += 1;
+        assert_eq!(*b, 101);
+    }
+
+    #[test]
+    fn drops_values() {
+        struct Counted<'a>(&'a Cell<usize>);
+        impl Drop for Counted<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let drops = Cell::new(0);
+        let pool: Pool<Counted, 1> = Pool::new();
+        let b1 = pool.alloc(Counted(&drops)).unwrap();
+        let b2 = pool.alloc(Counted(&drops)).unwrap();
+        drop(b1);
+        assert_eq!(drops.get(), 1);
+        drop(b2);
+        assert_eq!(drops.get(), 2);
+    }
+
+    #[test]
+    fn stale_handles() {
+        let mut pool: HandlePool<u32, 1> = HandlePool::new();
+        let h1 = pool.insert(1).unwrap();
+        let h2 = pool.insert(2).unwrap();
+        *pool.get_mut(h2).unwrap() += 1;
+        assert_eq!(pool.get(h2), Some(&3));
+
+        assert_eq!(pool.remove(h1), Some(1));
+        assert_eq!(pool.remove(h1), None);
+        let h3 = pool.insert(4).unwrap();
+        assert_eq!(h3.index(), h1.index());
+        assert_eq!(h3.generation(), h1.generation() + 1);
+        assert!(!pool.contains(h1));
+        assert_eq!(pool.get(h3), Some(&4));
+
+        let out_of_bounds = Handle {
+            index: HandlePool::<u32, 1>::CAPACITY,
+            generation: 0,
+        };
+        assert_eq!(pool.get(out_of_bounds), None);
+        let unused = Handle {
+            index: 5,
+            generation: 0,
+        };
+        assert_eq!(pool.get(unused), None);
+    }
+
+    #[test]
+    fn drops_remaining_values() {
+        struct Counted<'a>(&'a Cell<usize>);
+        impl Drop for Counted<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let drops = Cell::new(0);
+        let mut pool: HandlePool<Counted, 1> = HandlePool::new();
+        let h = pool.insert(Counted(&drops)).unwrap();
+        pool.insert(Counted(&drops)).unwrap();
+        drop(pool.remove(h));
+        assert_eq!(drops.get(), 1);
+        drop(pool);
+        assert_eq!(drops.get(), 2);
+    }
+}
@@ -29,6 +29,20 @@ static ALLOCATOR: WasmBumpAllocator = WasmBumpAllocator::with_memory();
 
 Note that `WasmBumpAllocator` respects the heap start address that is provided by the linker, making sure `static`s and other data doesn’t get corrupted by runtime allocations.
 
+## Listing allocations
+
+A bump allocator only knows how much memory is in use, not which allocations it is made of. To be able to list allocations, e.g. to hunt down leaks, [`TrackedBumpAllocator`] records the address and size of its first `N` allocations in a fixed-size side table:
+
+```rust
+use silly_alloc::bump::TrackedBumpAllocator;
+use core::alloc::{GlobalAlloc, Layout};
+
+let arena = [0u8; 1024];
+let allocator = TrackedBumpAllocator::<_, 128>::new(arena.as_slice());
+unsafe { allocator.alloc(Layout::new::<u64>()) };
+allocator.for_each_live(|ptr, size| println!("{:?}: {} bytes", ptr, size));
+```
+
 */
 use core::{
     alloc::{GlobalAlloc, Layout},
@@ -38,11 +52,16 @@ use core::{
     ptr::null_mut,
 };
 
+use crate::poison;
+
 pub mod head;
 #[cfg(feature = "atomics")]
 pub use head::ThreadSafeHead;
 pub use head::{Head, SingleThreadedHead};
 
+pub mod tracked;
+pub use tracked::TrackedBumpAllocator;
+
 #[cfg(target_family = "wasm")]
 pub mod wasm;
 #[cfg(all(target_family = "wasm", feature = "atomics"))]
@@ -157,7 +176,8 @@ impl<'a, M: BumpAllocatorArena, H: Head + Default> BumpAllocator<'a, M, H> {
     /// It’s the callers responsibility to ensure that there are no live values from the arena. Any leaked values will not have their `Drop` function called.
     pub unsafe fn reset(&self) {
         if let Some(head) = self.try_as_head_mut() {
-            head.set(self.memory.start() as usize);
+            poison::freed(self.memory.start() as *mut u8, head.num_bytes_used());
+            head.set(0);
         }
     }
 
@@ -166,10 +186,13 @@ impl<'a, M: BumpAllocatorArena, H: Head + Default> BumpAllocator<'a, M, H> {
         unsafe { Some(self.memory.start().offset(offset)) }
     }
 
+    // The head lives in an `UnsafeCell`, so handing out a mutable reference from `&self` is intentional. But Clippy is kicking off.
+    #[allow(clippy::mut_from_ref)]
     fn try_as_head_mut(&self) -> Option<&mut H> {
         unsafe { self.head.get().as_mut() }
     }
 
+    #[allow(clippy::mut_from_ref)]
     fn as_head_mut(&self) -> &mut H {
         self.try_as_head_mut().unwrap()
     }
@@ -220,7 +243,9 @@ unsafe impl<'a, M: BumpAllocatorArena, H: Head + Default> GlobalAlloc for BumpAl
         }
 
         head.bump(offset + size);
-        ptr.offset(offset.try_into().unwrap()) as *mut u8
+        let ptr = ptr.offset(offset.try_into().unwrap()) as *mut u8;
+        poison::fresh(ptr, size);
+        ptr
     }
 
     unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
@@ -233,6 +258,7 @@ mod tests {
     use xorshift;
 
     #[test]
+    #[allow(clippy::manual_is_multiple_of)]
     fn increment() {
         let arena = [0u8; 1024];
         {
@@ -280,6 +306,59 @@ mod tests {
         }
     }
 
+    #[test]
+    fn reset() {
+        let arena = [0u8; 16];
+        let allocator = SliceBumpAllocator::with_slice(arena.as_slice());
+        unsafe {
+            let ptr1 = allocator.alloc(Layout::from_size_align(16, 4).unwrap());
+            assert!(!ptr1.is_null());
+            allocator.reset();
+            assert_eq!(
+                allocator.alloc(Layout::from_size_align(16, 4).unwrap()),
+                ptr1
+            );
+        }
+    }
+
+    #[test]
+    fn reset_frees_whole_arena() {
+        let arena = [0u8; 16];
+        let allocator = SliceBumpAllocator::with_slice(arena.as_slice());
+        unsafe {
+            allocator.alloc(Layout::from_size_align(12, 4).unwrap());
+            allocator.reset();
+            // The head counts bytes used, so a reset must set it to zero rather than to the arena’s address.
+            for i in 0..4 {
+                let ptr = allocator.alloc(Layout::from_size_align(4, 4).unwrap());
+                assert_eq!(ptr as *const u8, arena.as_ptr().add(i * 4));
+            }
+            assert!(allocator
+                .alloc(Layout::from_size_align(1, 1).unwrap())
+                .is_null());
+        }
+    }
+
+    #[cfg(feature = "poison")]
+    #[test]
+    fn poison() {
+        use crate::poison::{FREED, FRESH};
+
+        let arena = [0u8; 16];
+        let allocator = SliceBumpAllocator::with_slice(arena.as_slice());
+        unsafe {
+            let ptr = allocator.alloc(Layout::from_size_align(8, 4).unwrap());
+            assert!(core::slice::from_raw_parts(ptr, 8)
+                .iter()
+                .all(|&b| b == FRESH));
+            allocator.reset();
+            assert!(core::slice::from_raw_parts(ptr, 8)
+                .iter()
+                .all(|&b| b == FREED));
+            assert_eq!(core::ptr::addr_of!(arena[8]).read_volatile(), 0);
+        }
+    }
+
     #[test]
     fn minifuzz() {
         const SIZE: usize = 1024 * 1024;
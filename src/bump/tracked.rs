@@ -0,0 +1,136 @@
+/*!
+Bump allocators that keep track of their allocations.
+*/
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    cell::UnsafeCell,
+    fmt::Debug,
+};
+
+use super::{BumpAllocator, BumpAllocatorArena, SingleThreadedHead};
+
+struct SideTable<const N: usize> {
+    /// Address and size of each allocation.
+    entries: [(usize, usize); N],
+    len: usize,
+    num_untracked: usize,
+}
+
+/// A [`BumpAllocator`] that records the address and size of its first `N` allocations in a side table, so that they can be listed with [`for_each_live`](Self::for_each_live).
+///
+/// Allocations beyond the first `N` still succeed, but are only counted. The side table is part of the allocator itself, so recording allocations never allocates.
+pub struct TrackedBumpAllocator<'a, M: BumpAllocatorArena, const N: usize> {
+    inner: BumpAllocator<'a, M, SingleThreadedHead>,
+    table: UnsafeCell<SideTable<N>>,
+}
+
+impl<'a, M: BumpAllocatorArena, const N: usize> TrackedBumpAllocator<'a, M, N> {
+    pub const fn new(memory: M) -> Self {
+        TrackedBumpAllocator {
+            inner: BumpAllocator::new(memory, SingleThreadedHead::new()),
+            table: UnsafeCell::new(SideTable {
+                entries: [(0, 0); N],
+                len: 0,
+                num_untracked: 0,
+            }),
+        }
+    }
+
+    /// Calls `f` with the address and size of every recorded allocation, in the order they were made.
+    pub fn for_each_live(&self, mut f: impl FnMut(*const u8, usize)) {
+        let table = unsafe { &*self.table.get() };
+        for &(addr, size) in &table.entries[..table.len] {
+            f(addr as *const u8, size);
+        }
+    }
+
+    /// Returns the number of allocations that didn’t fit into the side table.
+    pub fn num_untracked(&self) -> usize {
+        unsafe { (*self.table.get()).num_untracked }
+    }
+
+    /// Resets the arena and the side table to their initial state of being completely unused.
+    ///
+    /// # Safety
+    /// It’s the callers responsibility to ensure that there are no live values from the arena. Any leaked values will not have their `Drop` function called.
+    pub unsafe fn reset(&self) {
+        self.inner.reset();
+        let table = &mut *self.table.get();
+        table.len = 0;
+        table.num_untracked = 0;
+    }
+
+    pub fn arena(&self) -> &dyn BumpAllocatorArena {
+        self.inner.arena()
+    }
+}
+
+impl<'a, M: BumpAllocatorArena, const N: usize> Debug for TrackedBumpAllocator<'a, M, N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let table = unsafe { &*self.table.get() };
+        f.debug_struct("TrackedBumpAllocator")
+            .field("inner", &self.inner)
+            .field("tracked", &table.len)
+            .field("untracked", &table.num_untracked)
+            .finish()
+    }
+}
+
+unsafe impl<'a, M: BumpAllocatorArena, const N: usize> Sync for TrackedBumpAllocator<'a, M, N> {}
+
+unsafe impl<'a, M: BumpAllocatorArena, const N: usize> GlobalAlloc
+    for TrackedBumpAllocator<'a, M, N>
+{
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if ptr.is_null() {
+            return ptr;
+        }
+        let table = &mut *self.table.get();
+        if table.len < N {
+            table.entries[table.len] = (ptr as usize, layout.size());
+            table.len += 1;
+        } else {
+            table.num_untracked += 1;
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_allocations() {
+        let arena = [0u8; 1024];
+        let allocator = TrackedBumpAllocator::<_, 2>::new(arena.as_slice());
+        unsafe {
+            let ptr1 = allocator.alloc(Layout::from_size_align(3, 4).unwrap());
+            let ptr2 = allocator.alloc(Layout::from_size_align(16, 8).unwrap());
+            allocator.alloc(Layout::from_size_align(1, 1).unwrap());
+
+            let mut live = [(core::ptr::null(), 0); 2];
+            let mut num_live = 0;
+            allocator.for_each_live(|ptr, size| {
+                live[num_live] = (ptr, size);
+                num_live += 1;
+            });
+            assert_eq!(num_live, 2);
+            assert_eq!(live, [(ptr1 as *const u8, 3), (ptr2 as *const u8, 16)]);
+            assert_eq!(allocator.num_untracked(), 1);
+
+            allocator.reset();
+            allocator.for_each_live(|_, _| panic!("No allocations expected after a reset"));
+            assert_eq!(allocator.num_untracked(), 0);
+            // The arena is reused after a reset.
+            assert_eq!(
+                allocator.alloc(Layout::from_size_align(3, 4).unwrap()),
+                ptr1
+            );
+        }
+    }
+}
@@ -0,0 +1,412 @@
+/*!
+C ABI allocation functions.
+
+[`export_c_abi!`](crate::export_c_abi) exports `malloc`, `calloc`, `realloc`, `free`, `posix_memalign` and `aligned_alloc` backed by the given allocator, so that C code linked into the same binary, e.g. a WebAssembly module, shares it with Rust instead of bringing its own allocator:
+
+```rust,no_run
+use silly_alloc::bucket_allocator;
+
+// Every allocation carries a 16-byte header, so these buckets serve
+// `malloc` calls of up to 48 and 4080 bytes respectively.
+#[bucket_allocator]
+struct MyBucketAllocator {
+    small: Bucket<SlotSize<64>, NumSlots<1024>, Align<16>>,
+    overflow: Bucket<SlotSize<4096>, NumSlots<64>, Align<16>>,
+}
+
+#[global_allocator]
+static ALLOCATOR: MyBucketAllocator = MyBucketAllocator::new();
+
+// Also serves all `malloc` calls from C.
+silly_alloc::export_c_abi!(ALLOCATOR);
+```
+
+The exported functions call the allocator directly. It doesn’t have to be the global allocator, but it must not be built on top of `malloc` itself, like [`System`](https://doc.rust-lang.org/std/alloc/struct.System.html) usually is. The functions in this module implement the exports for any allocator and can also be called from Rust.
+
+As `free` doesn’t get the size of the allocation, every allocation is preceded by a header that stores the [`Layout`] of the underlying Rust allocation. The header is [`MIN_ALIGN`] bytes big, or as big as the alignment for over-aligned allocations, so every allocation needs that many bytes more than requested. Bucket sizes have to account for that. Allocations are aligned to at least [`MIN_ALIGN`], which is the alignment of `max_align_t`.
+
+Like their C counterparts, the functions set `errno` when they fail, on targets whose libc provides it.
+*/
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    ffi::{c_int, c_void},
+    mem::size_of,
+    ptr::null_mut,
+};
+
+/// The alignment of `max_align_t`, which `malloc` guarantees for all allocations.
+pub const MIN_ALIGN: usize = 16;
+
+#[cfg(target_os = "wasi")]
+const EINVAL: c_int = 28;
+#[cfg(target_os = "wasi")]
+const ENOMEM: c_int = 48;
+#[cfg(not(target_os = "wasi"))]
+const EINVAL: c_int = 22;
+#[cfg(not(target_os = "wasi"))]
+const ENOMEM: c_int = 12;
+
+#[cfg(any(target_os = "linux", target_os = "wasi"))]
+extern "C" {
+    #[link_name = "__errno_location"]
+    fn errno_location() -> *mut c_int;
+}
+#[cfg(any(target_os = "android", target_os = "netbsd", target_os = "openbsd"))]
+extern "C" {
+    #[link_name = "__errno"]
+    fn errno_location() -> *mut c_int;
+}
+#[cfg(any(
+    target_vendor = "apple",
+    target_os = "freebsd",
+    target_os = "dragonfly"
+))]
+extern "C" {
+    #[link_name = "__error"]
+    fn errno_location() -> *mut c_int;
+}
+/// Targets without a libc have no `errno`.
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "wasi",
+    target_os = "android",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_vendor = "apple",
+    target_os = "freebsd",
+    target_os = "dragonfly"
+)))]
+unsafe fn errno_location() -> *mut c_int {
+    null_mut()
+}
+
+fn set_errno(value: c_int) {
+    let errno = unsafe { errno_location() };
+    if !errno.is_null() {
+        unsafe { errno.write(value) };
+    }
+}
+
+/// Stored right in front of every allocation.
+#[derive(Clone, Copy)]
+struct Header {
+    /// Size of the underlying allocation, including the header.
+    size: usize,
+    align: usize,
+}
+
+const _: () = assert!(size_of::<Header>() <= MIN_ALIGN);
+
+impl Header {
+    /// Number of bytes in front of an allocation with alignment `align`, which is at least [`MIN_ALIGN`] and a multiple of `align`.
+    fn offset(align: usize) -> usize {
+        align.max(MIN_ALIGN)
+    }
+
+    fn layout(&self) -> Layout {
+        unsafe { Layout::from_size_align_unchecked(self.size, self.align) }
+    }
+
+    unsafe fn of(ptr: *mut c_void) -> *mut Header {
+        (ptr as *mut Header).sub(1)
+    }
+}
+
+/// Allocates `size` bytes aligned to `align`, which has to be a power of two. Sets `errno` to `ENOMEM` on failure.
+unsafe fn alloc_with_header<A: GlobalAlloc>(
+    allocator: &A,
+    size: usize,
+    align: usize,
+    zeroed: bool,
+) -> *mut c_void {
+    let align = align.max(MIN_ALIGN);
+    let offset = Header::offset(align);
+    let Some(layout) = size
+        .checked_add(offset)
+        .and_then(|size| Layout::from_size_align(size, align).ok())
+    else {
+        set_errno(ENOMEM);
+        return null_mut();
+    };
+    let base = if zeroed {
+        allocator.alloc_zeroed(layout)
+    } else {
+        allocator.alloc(layout)
+    };
+    if base.is_null() {
+        set_errno(ENOMEM);
+        return null_mut();
+    }
+    let ptr = base.add(offset) as *mut c_void;
+    Header::of(ptr).write(Header {
+        size: layout.size(),
+        align,
+    });
+    ptr
+}
+
+/// Like C’s `malloc`.
+///
+/// # Safety
+/// `allocator` must not be built on top of `malloc`. The allocation must be freed with [`free`] or [`realloc`] using the same allocator.
+pub unsafe fn malloc<A: GlobalAlloc>(allocator: &A, size: usize) -> *mut c_void {
+    alloc_with_header(allocator, size, MIN_ALIGN, false)
+}
+
+/// Like C’s `calloc`. Returns a null pointer if `num * size` overflows.
+///
+/// # Safety
+/// See [`malloc`].
+pub unsafe fn calloc<A: GlobalAlloc>(allocator: &A, num: usize, size: usize) -> *mut c_void {
+    let Some(size) = num.checked_mul(size) else {
+        set_errno(ENOMEM);
+        return null_mut();
+    };
+    alloc_with_header(allocator, size, MIN_ALIGN, true)
+}
+
+/// Like C’s `free`.
+///
+/// # Safety
+/// `ptr` must be null or an allocation returned by one of the functions in this module with the same allocator that hasn’t been freed yet.
+pub unsafe fn free<A: GlobalAlloc>(allocator: &A, ptr: *mut c_void) {
+    if ptr.is_null() {
+        return;
+    }
+    let header = Header::of(ptr).read();
+    allocator.dealloc(
+        (ptr as *mut u8).sub(Header::offset(header.align)),
+        header.layout(),
+    );
+}
+
+/// Like C’s `realloc`. A `size` of zero frees the allocation and returns a null pointer.
+///
+/// # Safety
+/// See [`free`].
+pub unsafe fn realloc<A: GlobalAlloc>(allocator: &A, ptr: *mut c_void, size: usize) -> *mut c_void {
+    if ptr.is_null() {
+        return malloc(allocator, size);
+    }
+    if size == 0 {
+        free(allocator, ptr);
+        return null_mut();
+    }
+    let header = Header::of(ptr).read();
+    let offset = Header::offset(header.align);
+    let Some(new_size) = size
+        .checked_add(offset)
+        .filter(|&size| Layout::from_size_align(size, header.align).is_ok())
+    else {
+        set_errno(ENOMEM);
+        return null_mut();
+    };
+    let base = allocator.realloc((ptr as *mut u8).sub(offset), header.layout(), new_size);
+    if base.is_null() {
+        set_errno(ENOMEM);
+        return null_mut();
+    }
+    let ptr = base.add(offset) as *mut c_void;
+    Header::of(ptr).write(Header {
+        size: new_size,
+        align: header.align,
+    });
+    ptr
+}
+
+/// Like POSIX’s `posix_memalign`. `align` must be a power of two and a multiple of the pointer size.
+///
+/// # Safety
+/// `memptr` must be valid for writes. See [`malloc`].
+pub unsafe fn posix_memalign<A: GlobalAlloc>(
+    allocator: &A,
+    memptr: *mut *mut c_void,
+    align: usize,
+    size: usize,
+) -> c_int {
+    if !align.is_power_of_two() || !align.is_multiple_of(size_of::<*mut c_void>()) {
+        return EINVAL;
+    }
+    let ptr = alloc_with_header(allocator, size, align, false);
+    if ptr.is_null() {
+        return ENOMEM;
+    }
+    *memptr = ptr;
+    0
+}
+
+/// Like C’s `aligned_alloc`. `align` must be a power of two.
+///
+/// # Safety
+/// See [`malloc`].
+pub unsafe fn aligned_alloc<A: GlobalAlloc>(
+    allocator: &A,
+    align: usize,
+    size: usize,
+) -> *mut c_void {
+    if !align.is_power_of_two() {
+        set_errno(EINVAL);
+        return null_mut();
+    }
+    alloc_with_header(allocator, size, align, false)
+}
+
+/// Exports the C allocation functions of [`c_abi`](crate::c_abi), backed by the allocator in the given static.
+///
+/// The allocator must not be built on top of `malloc`, or the exports end up calling themselves. A binary must export the functions only once.
+#[macro_export]
+macro_rules! export_c_abi {
+    ($allocator:path) => {
+        const _: () = {
+            use ::core::ffi::{c_int, c_void};
+
+            #[no_mangle]
+            pub unsafe extern "C" fn malloc(size: usize) -> *mut c_void {
+                $crate::c_abi::malloc(&$allocator, size)
+            }
+
+            #[no_mangle]
+            pub unsafe extern "C" fn calloc(num: usize, size: usize) -> *mut c_void {
+                $crate::c_abi::calloc(&$allocator, num, size)
+            }
+
+            #[no_mangle]
+            pub unsafe extern "C" fn free(ptr: *mut c_void) {
+                $crate::c_abi::free(&$allocator, ptr)
+            }
+
+            #[no_mangle]
+            pub unsafe extern "C" fn realloc(ptr: *mut c_void, size: usize) -> *mut c_void {
+                $crate::c_abi::realloc(&$allocator, ptr, size)
+            }
+
+            #[no_mangle]
+            pub unsafe extern "C" fn posix_memalign(
+                memptr: *mut *mut c_void,
+                align: usize,
+                size: usize,
+            ) -> c_int {
+                $crate::c_abi::posix_memalign(&$allocator, memptr, align, size)
+            }
+
+            #[no_mangle]
+            pub unsafe extern "C" fn aligned_alloc(align: usize, size: usize) -> *mut c_void {
+                $crate::c_abi::aligned_alloc(&$allocator, align, size)
+            }
+        };
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::alloc::System;
+
+    // Calling the functions with `System` is fine, as long as they aren’t exported.
+    unsafe fn malloc(size: usize) -> *mut c_void {
+        super::malloc(&System, size)
+    }
+
+    unsafe fn calloc(num: usize, size: usize) -> *mut c_void {
+        super::calloc(&System, num, size)
+    }
+
+    unsafe fn free(ptr: *mut c_void) {
+        super::free(&System, ptr)
+    }
+
+    unsafe fn realloc(ptr: *mut c_void, size: usize) -> *mut c_void {
+        super::realloc(&System, ptr, size)
+    }
+
+    unsafe fn posix_memalign(memptr: *mut *mut c_void, align: usize, size: usize) -> c_int {
+        super::posix_memalign(&System, memptr, align, size)
+    }
+
+    unsafe fn aligned_alloc(align: usize, size: usize) -> *mut c_void {
+        super::aligned_alloc(&System, align, size)
+    }
+
+    #[test]
+    fn malloc_free() {
+        unsafe {
+            let ptrs: [*mut c_void; 8] = core::array::from_fn(|i| malloc(i * 7));
+            for (i, &ptr) in ptrs.iter().enumerate() {
+                assert!(!ptr.is_null());
+                assert_eq!(ptr as usize % MIN_ALIGN, 0);
+                (ptr as *mut u8).write_bytes(i as u8, i * 7);
+            }
+            for ptr in ptrs {
+                free(ptr);
+            }
+            free(null_mut());
+        }
+    }
+
+    #[test]
+    fn calloc_zeroes() {
+        unsafe {
+            let ptr = calloc(16, 4) as *mut u8;
+            assert!(core::slice::from_raw_parts(ptr, 64).iter().all(|&b| b == 0));
+            free(ptr as *mut c_void);
+            assert!(calloc(usize::MAX, 2).is_null());
+        }
+    }
+
+    #[test]
+    fn realloc_keeps_contents() {
+        unsafe {
+            let ptr = realloc(null_mut(), 4) as *mut u8;
+            ptr.copy_from([1, 2, 3, 4].as_ptr(), 4);
+            let ptr = realloc(ptr as *mut c_void, 1000) as *mut u8;
+            assert_eq!(core::slice::from_raw_parts(ptr, 4), [1, 2, 3, 4]);
+            let ptr = realloc(ptr as *mut c_void, 2) as *mut u8;
+            assert_eq!(core::slice::from_raw_parts(ptr, 2), [1, 2]);
+            assert!(realloc(ptr as *mut c_void, 0).is_null());
+        }
+    }
+
+    #[test]
+    fn over_aligned() {
+        unsafe {
+            let mut ptr = null_mut();
+            assert_eq!(posix_memalign(&mut ptr, 256, 10), 0);
+            assert_eq!(ptr as usize % 256, 0);
+            let ptr = realloc(ptr, 500);
+            assert_eq!(ptr as usize % 256, 0);
+            free(ptr);
+            let mut ptr = null_mut();
+            assert_eq!(posix_memalign(&mut ptr, 3, 10), EINVAL);
+            assert_eq!(posix_memalign(&mut ptr, 2, 10), EINVAL);
+
+            let ptr = aligned_alloc(4096, 4096);
+            assert_eq!(ptr as usize % 4096, 0);
+            free(ptr);
+            assert!(aligned_alloc(12, 24).is_null());
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn sets_errno() {
+        let errno = || std::io::Error::last_os_error().raw_os_error();
+        unsafe {
+            set_errno(0);
+            assert!(malloc(usize::MAX).is_null());
+            assert_eq!(errno(), Some(ENOMEM));
+            set_errno(0);
+            assert!(calloc(usize::MAX, 2).is_null());
+            assert_eq!(errno(), Some(ENOMEM));
+            set_errno(0);
+            let ptr = malloc(8);
+            assert!(realloc(ptr, usize::MAX).is_null());
+            assert_eq!(errno(), Some(ENOMEM));
+            free(ptr);
+            set_errno(0);
+            assert!(aligned_alloc(12, 24).is_null());
+            assert_eq!(errno(), Some(EINVAL));
+        }
+    }
+}
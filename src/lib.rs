@@ -7,6 +7,11 @@
 
 - Bump allocators — Fast and small allocators that cannot free memory.
 - Bucket allocators — Alloctors that excel at frequent allocations and deallocations of a similar size.
+- Optional memory poisoning (`poison` feature) to catch use after free.
+- Redzones around allocations to find buffer overruns.
+- A recording allocator that suggests a bucket allocator configuration for a workload.
+- A tracing allocator that logs allocation events into a ring buffer in a compact binary format.
+- Optional C ABI `malloc` and `free` exports (`c-abi` feature), so C code can share an allocator with Rust.
 - Works with `#![no_std]`
 - Support for and tests on `wasm32-unknown-unknown` and `wasm32-wasi`.
 
@@ -27,10 +32,10 @@ To run the unit and integration tests:
 $ cargo test --target=wasm32-wasi
 ```
 
-To run the doc tests, Nightly Rust is required (as cross-compiling doc tests is still experimental) and a special environment variable needs to be set so that the macro crate generates the correct absolute paths for the bucket allocator types.
+To run the doc tests, Nightly Rust is required (as cross-compiling doc tests is still experimental).
 
 ```shell
-$ SILLY_ALLOC_DOC_TESTS=1 cargo +nightly test --doc --target wasm32-wasi -Zdoctest-xcompile
+$ cargo +nightly test --doc --target wasm32-wasi -Zdoctest-xcompile
 ```
 */
 
@@ -41,9 +46,28 @@ pub use bump::SliceBumpAllocator;
 pub use bump::WasmBumpAllocator;
 
 pub mod bucket;
+pub use bucket::BucketAllocator;
+
+mod abort;
+
+pub mod poison;
+
+pub mod redzone;
+
+pub mod recording;
+
+// Sequence numbers are 64 bits wide, so they don’t wrap around on 32-bit targets.
+#[cfg(all(feature = "atomics", target_has_atomic = "64"))]
+pub mod trace;
+
+#[cfg(feature = "c-abi")]
+pub mod c_abi;
 
 pub use silly_alloc_macros::bucket_allocator;
 
+// Lets the code generated by `bucket_allocator` refer to `::silly_alloc` from within this crate.
+extern crate self as silly_alloc;
+
 // Enable std for testing
 #[cfg(test)]
 #[macro_use]
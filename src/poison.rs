@@ -0,0 +1,58 @@
+/*!
+Memory poisoning for debugging.
+
+With the `poison` feature enabled, the allocators fill memory with recognizable patterns: fresh allocations are filled with [`FRESH`], freed memory with [`FREED`]. Reading these patterns where real data is expected hints at use of uninitialized memory or use after free. When a bucket slot is reused, it is checked to still hold [`FREED`], which catches writes after free. Bucket slots that have never been claimed are poisoned right before their first use, so all free slots are checked. A detected write after free aborts the program, as allocators must not unwind.
+
+Without the `poison` feature, all of this compiles to nothing.
+*/
+
+/// Pattern that fresh allocations are filled with.
+pub const FRESH: u8 = 0xCD;
+/// Pattern that freed memory is filled with.
+pub const FREED: u8 = 0xDD;
+
+/// Fills fresh memory with [`FRESH`].
+///
+/// # Safety
+/// `ptr` must be valid for writes of `len` bytes.
+#[inline(always)]
+pub(crate) unsafe fn fresh(ptr: *mut u8, len: usize) {
+    if cfg!(feature = "poison") {
+        ptr.write_bytes(FRESH, len);
+    }
+}
+
+/// Fills freed memory with [`FREED`].
+///
+/// # Safety
+/// `ptr` must be valid for writes of `len` bytes.
+#[inline(always)]
+pub(crate) unsafe fn freed(ptr: *mut u8, len: usize) {
+    if cfg!(feature = "poison") {
+        ptr.write_bytes(FREED, len);
+    }
+}
+
+/// Whether freed memory still holds [`FREED`] everywhere, i.e. hasn’t been written to since it was freed.
+///
+/// # Safety
+/// `ptr` must be valid for reads of `len` bytes.
+pub(crate) unsafe fn is_freed(ptr: *const u8, len: usize) -> bool {
+    core::slice::from_raw_parts(ptr, len)
+        .iter()
+        .all(|&b| b == FREED)
+}
+
+/// Aborts if memory that is about to be reused has been written to since it was freed.
+///
+/// # Safety
+/// `ptr` must be valid for reads of `len` bytes.
+#[inline(always)]
+pub(crate) unsafe fn check_freed(ptr: *const u8, len: usize) {
+    if cfg!(feature = "poison") && !is_freed(ptr, len) {
+        crate::abort::abort(format_args!(
+            "Memory at {:p} was written to after being freed",
+            ptr
+        ));
+    }
+}
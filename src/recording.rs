@@ -0,0 +1,264 @@
+/*!
+Recording allocation behavior to tune bucket allocators.
+
+[`RecordingAllocator`] wraps any other allocator and keeps a histogram of all allocations, grouped into size classes of powers of two. For each size class, it tracks the number of allocations, the biggest alignment requested and the peak number of allocations that were live at the same time. After running a representative workload, [`RecordingAllocator::write_bucket_allocator`] turns the histogram into a `#[bucket_allocator]` definition that would have served it:
+
+```rust
+use core::alloc::{GlobalAlloc, Layout};
+use silly_alloc::{recording::RecordingAllocator, SliceBumpAllocator};
+
+let arena = [0u8; 4096];
+let allocator = RecordingAllocator::new(SliceBumpAllocator::with_slice(arena.as_slice()));
+
+// Run the workload…
+for size in [8, 8, 24, 100] {
+    unsafe { allocator.alloc(Layout::from_size_align(size, 8).unwrap()) };
+}
+
+let mut config = String::new();
+allocator.write_bucket_allocator(&mut config, "MyBucketAllocator", 1.5).unwrap();
+println!("{}", config);
+```
+
+Recording doesn’t allocate, so the recorder can be used as the global allocator of a single-threaded program, e.g. on WebAssembly. Like the other allocators of this crate, it is not thread-safe: The histogram is not synchronized, so it must not be used from multiple threads.
+*/
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    cell::UnsafeCell,
+    fmt::{Debug, Write},
+};
+
+use crate::bucket::MAX_ALIGN;
+
+/// Number of size classes. Allocations are at most `isize::MAX` bytes, so the biggest class is `1 << (usize::BITS - 1)`.
+const NUM_CLASSES: usize = usize::BITS as usize;
+
+/// Recorded allocations of one size class.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SizeClassStats {
+    /// Biggest allocation size in this class, which is a power of two.
+    pub size: usize,
+    /// Number of allocations in this class.
+    pub allocations: usize,
+    /// Number of allocations that are currently live.
+    pub live: usize,
+    /// Highest number of allocations that have been live at the same time.
+    pub peak_live: usize,
+    /// Biggest alignment requested by an allocation in this class.
+    pub max_align: usize,
+}
+
+impl SizeClassStats {
+    const fn new(size: usize) -> Self {
+        SizeClassStats {
+            size,
+            allocations: 0,
+            live: 0,
+            peak_live: 0,
+            max_align: 0,
+        }
+    }
+}
+
+fn class_idx(size: usize) -> usize {
+    // Sizes that are no power of two are rounded up. `size.next_power_of_two()` would overflow for the biggest sizes.
+    match size {
+        0 | 1 => 0,
+        size => (usize::BITS - (size - 1).leading_zeros()) as usize,
+    }
+}
+
+/// An allocator that forwards to `A` and records a histogram of all allocations.
+pub struct RecordingAllocator<A: GlobalAlloc> {
+    inner: A,
+    classes: UnsafeCell<[SizeClassStats; NUM_CLASSES]>,
+}
+
+impl<A: GlobalAlloc> RecordingAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        let mut classes = [SizeClassStats::new(0); NUM_CLASSES];
+        let mut i = 0;
+        while i < NUM_CLASSES {
+            classes[i].size = 1 << i;
+            i += 1;
+        }
+        RecordingAllocator {
+            inner,
+            classes: UnsafeCell::new(classes),
+        }
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Calls `f` with the stats of every size class that has seen allocations, from the smallest size to the biggest.
+    pub fn for_each_class(&self, f: impl FnMut(&SizeClassStats)) {
+        let classes = unsafe { &*self.classes.get() };
+        classes
+            .iter()
+            .filter(|class| class.allocations > 0)
+            .for_each(f);
+    }
+
+    /// Forgets all recorded allocations.
+    pub fn clear(&self) {
+        for class in unsafe { &mut *self.classes.get() } {
+            *class = SizeClassStats::new(class.size);
+        }
+    }
+
+    fn record_alloc(&self, layout: Layout) {
+        let class = unsafe { &mut (*self.classes.get())[class_idx(layout.size())] };
+        class.allocations += 1;
+        class.live += 1;
+        class.peak_live = class.peak_live.max(class.live);
+        class.max_align = class.max_align.max(layout.align());
+    }
+
+    fn record_free(&self, layout: Layout) {
+        let class = unsafe { &mut (*self.classes.get())[class_idx(layout.size())] };
+        // Allocations from before the last `clear()` are not live as far as the histogram is concerned.
+        class.live = class.live.saturating_sub(1);
+    }
+
+    /// Writes a `#[bucket_allocator]` struct definition with one bucket per recorded size class.
+    ///
+    /// Each bucket gets enough slots for the peak number of live allocations of its size class, multiplied by `headroom`. Slot sizes are raised to the biggest alignment requested in the class, as slot sizes must be a multiple of the alignment. Size classes with alignments the bucket allocators don’t support are skipped.
+    pub fn write_bucket_allocator(
+        &self,
+        w: &mut impl Write,
+        name: &str,
+        headroom: f32,
+    ) -> core::fmt::Result {
+        writeln!(w, "#[bucket_allocator]")?;
+        writeln!(w, "struct {} {{", name)?;
+        let mut result = Ok(());
+        self.for_each_class(|class| {
+            if result.is_err() || class.max_align > MAX_ALIGN {
+                return;
+            }
+            let slot_size = class.size.max(class.max_align);
+            let num_slots = ((class.peak_live as f32 * headroom) as usize).max(1);
+            // `f32::ceil` is not available without `std`.
+            let num_slots = if (num_slots as f32) < class.peak_live as f32 * headroom {
+                num_slots + 1
+            } else {
+                num_slots
+            };
+            result = writeln!(
+                w,
+                "    class_{}: Bucket<SlotSize<{}>, NumSlots<{}>, Align<{}>>,",
+                class.size, slot_size, num_slots, class.max_align
+            );
+        });
+        result?;
+        writeln!(w, "}}")
+    }
+}
+
+impl<A: GlobalAlloc + Debug> Debug for RecordingAllocator<A> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RecordingAllocator")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+unsafe impl<A: GlobalAlloc + Sync> Sync for RecordingAllocator<A> {}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for RecordingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            self.record_alloc(layout);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout);
+        self.record_free(layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            self.record_free(layout);
+            self.record_alloc(Layout::from_size_align_unchecked(new_size, layout.align()));
+        }
+        new_ptr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::string::String;
+
+    use crate::SliceBumpAllocator;
+
+    #[test]
+    fn size_classes() {
+        assert_eq!(class_idx(0), 0);
+        assert_eq!(class_idx(1), 0);
+        assert_eq!(class_idx(2), 1);
+        assert_eq!(class_idx(3), 2);
+        assert_eq!(class_idx(4), 2);
+        assert_eq!(class_idx(5), 3);
+        assert_eq!(class_idx(isize::MAX as usize), NUM_CLASSES - 1);
+    }
+
+    #[test]
+    fn bucket_allocator_config() {
+        let arena = [0u8; 1024];
+        let allocator = RecordingAllocator::new(SliceBumpAllocator::with_slice(arena.as_slice()));
+        unsafe {
+            let l3 = Layout::from_size_align(3, 1).unwrap();
+            let l4 = Layout::from_size_align(4, 4).unwrap();
+            let l12 = Layout::from_size_align(12, 32).unwrap();
+            let ptrs: [*mut u8; 3] = core::array::from_fn(|_| allocator.alloc(l3));
+            for ptr in ptrs {
+                allocator.dealloc(ptr, l3);
+            }
+            allocator.alloc(l4);
+            allocator.alloc(l4);
+            allocator.alloc(l12);
+        }
+
+        let mut classes = [SizeClassStats::default(); 2];
+        let mut num_classes = 0;
+        allocator.for_each_class(|class| {
+            if num_classes < 2 {
+                classes[num_classes] = *class;
+            }
+            num_classes += 1;
+        });
+        assert_eq!(num_classes, 2);
+        assert_eq!(
+            classes[0],
+            SizeClassStats {
+                size: 4,
+                allocations: 5,
+                live: 2,
+                peak_live: 3,
+                max_align: 4,
+            }
+        );
+
+        let mut config = String::new();
+        allocator
+            .write_bucket_allocator(&mut config, "MyBucketAllocator", 1.5)
+            .unwrap();
+        assert_eq!(
+            config,
+            "#[bucket_allocator]\n\
+            struct MyBucketAllocator {\n    \
+                class_4: Bucket<SlotSize<4>, NumSlots<5>, Align<4>>,\n    \
+                class_16: Bucket<SlotSize<32>, NumSlots<2>, Align<32>>,\n\
+            }\n"
+        );
+    }
+}
@@ -0,0 +1,242 @@
+/*!
+Redzones to find buffer overruns.
+
+[`RedzoneAllocator`] wraps any other allocator and surrounds every allocation with `N` bytes of [`CANARY`] on either side. When an allocation is freed or reallocated, both redzones are checked and any corruption is reported, naming the size of the allocation and which side was hit. This catches out-of-bounds writes in places where tools like AddressSanitizer aren’t available, like WebAssembly.
+
+```rust
+use core::alloc::{GlobalAlloc, Layout};
+use silly_alloc::{bucket_allocator, redzone::RedzoneAllocator};
+
+#[bucket_allocator]
+struct MyBucketAllocator {
+    vec4: Bucket<SlotSize<64>, NumSlots<128>, Align<16>>,
+    overflow: Bucket<SlotSize<256>, NumSlots<64>, Align<16>>,
+}
+
+static ALLOCATOR: RedzoneAllocator<MyBucketAllocator> =
+    RedzoneAllocator::new(MyBucketAllocator::new());
+
+unsafe {
+    let layout = Layout::new::<[u8; 4]>();
+    let ptr = ALLOCATOR.alloc(layout);
+    ptr.write_bytes(0, 4);
+    ALLOCATOR.dealloc(ptr, layout);
+}
+```
+
+By default, a corrupted redzone aborts the program. A different report function can be provided with [`RedzoneAllocator::with_report`], e.g. to log violations and carry on. Report functions are called from within the allocator, so they must not unwind. The memory is freed after the report, unless the report aborts.
+
+Each allocation grows by `N` bytes after it and by `N` rounded up to the allocation’s alignment before it, so the wrapped allocator has to be configured for the bigger sizes.
+*/
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    fmt::{Debug, Display},
+    ptr::null_mut,
+};
+
+/// Pattern that redzones are filled with.
+pub const CANARY: u8 = 0xFD;
+
+/// The side of an allocation a redzone is on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Before,
+    After,
+}
+
+/// A redzone that was found to be corrupted.
+#[derive(Clone, Copy, Debug)]
+pub struct Violation {
+    /// Pointer to the allocation, as returned to the user.
+    pub ptr: *const u8,
+    /// Size of the allocation.
+    pub size: usize,
+    pub side: Side,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let side = match self.side {
+            Side::Before => "before",
+            Side::After => "after",
+        };
+        write!(
+            f,
+            "Redzone {} the {}-byte allocation at {:p} was overwritten",
+            side, self.size, self.ptr
+        )
+    }
+}
+
+fn abort_report(violation: &Violation) {
+    crate::abort::abort(format_args!("{}", violation));
+}
+
+/// An allocator that surrounds each allocation of the wrapped allocator `A` with `N` bytes of redzone on either side.
+pub struct RedzoneAllocator<A: GlobalAlloc, const N: usize = 16> {
+    inner: A,
+    report: fn(&Violation),
+}
+
+impl<A: GlobalAlloc, const N: usize> RedzoneAllocator<A, N> {
+    /// Wraps `inner`, aborting when a corrupted redzone is found.
+    pub const fn new(inner: A) -> Self {
+        Self::with_report(inner, abort_report)
+    }
+
+    /// Wraps `inner`, calling `report` when a corrupted redzone is found. `report` must not unwind, as allocators must not unwind.
+    pub const fn with_report(inner: A, report: fn(&Violation)) -> Self {
+        RedzoneAllocator { inner, report }
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Size of the redzone before an allocation, which is rounded up so that the allocation stays aligned.
+    fn front_size(layout: Layout) -> usize {
+        N.next_multiple_of(layout.align())
+    }
+
+    fn outer_layout(layout: Layout) -> Option<Layout> {
+        let size = Self::front_size(layout)
+            .checked_add(layout.size())?
+            .checked_add(N)?;
+        Layout::from_size_align(size, layout.align()).ok()
+    }
+
+    /// Checks both redzones of the allocation at `ptr` and reports any corruption.
+    ///
+    /// # Safety
+    /// `ptr` must have been returned by this allocator for `layout`.
+    pub unsafe fn check(&self, ptr: *const u8, layout: Layout) {
+        let front = Self::front_size(layout);
+        let before = core::slice::from_raw_parts(ptr.sub(front), front);
+        let after = core::slice::from_raw_parts(ptr.add(layout.size()), N);
+        for (zone, side) in [(before, Side::Before), (after, Side::After)] {
+            if zone.iter().any(|&b| b != CANARY) {
+                (self.report)(&Violation {
+                    ptr,
+                    size: layout.size(),
+                    side,
+                });
+            }
+        }
+    }
+}
+
+impl<A: GlobalAlloc + Debug, const N: usize> Debug for RedzoneAllocator<A, N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RedzoneAllocator")
+            .field("inner", &self.inner)
+            .field("redzone_size", &N)
+            .finish()
+    }
+}
+
+unsafe impl<A: GlobalAlloc, const N: usize> GlobalAlloc for RedzoneAllocator<A, N> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let Some(outer) = Self::outer_layout(layout) else {
+            return null_mut();
+        };
+        let base = self.inner.alloc(outer);
+        if base.is_null() {
+            return null_mut();
+        }
+        let front = Self::front_size(layout);
+        base.write_bytes(CANARY, front);
+        let ptr = base.add(front);
+        ptr.add(layout.size()).write_bytes(CANARY, N);
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.check(ptr, layout);
+        // The layout was valid when the allocation was made.
+        let outer = Self::outer_layout(layout).unwrap_unchecked();
+        self.inner.dealloc(ptr.sub(Self::front_size(layout)), outer);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let new_ptr = self.alloc(new_layout);
+        if !new_ptr.is_null() {
+            new_ptr.copy_from_nonoverlapping(ptr, layout.size().min(new_size));
+            self.dealloc(ptr, layout);
+        }
+        new_ptr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::SliceBumpAllocator;
+
+    #[test]
+    fn intact() {
+        let arena = [0u8; 256];
+        let allocator: RedzoneAllocator<_> =
+            RedzoneAllocator::new(SliceBumpAllocator::with_slice(arena.as_slice()));
+        unsafe {
+            let layout = Layout::from_size_align(12, 32).unwrap();
+            let ptr = allocator.alloc(layout);
+            assert!(!ptr.is_null());
+            assert_eq!(ptr as usize % 32, 0);
+            ptr.write_bytes(1, 12);
+            let ptr = allocator.realloc(ptr, layout, 20);
+            assert!(!ptr.is_null());
+            assert_eq!(ptr as usize % 32, 0);
+            assert_eq!(core::slice::from_raw_parts(ptr, 12), &[1; 12]);
+            allocator.dealloc(ptr, Layout::from_size_align(20, 32).unwrap());
+        }
+    }
+
+    #[test]
+    fn reports_overruns() {
+        static BEFORE: AtomicUsize = AtomicUsize::new(0);
+        static AFTER: AtomicUsize = AtomicUsize::new(0);
+        fn report(violation: &Violation) {
+            assert_eq!(violation.size, 12);
+            match violation.side {
+                Side::Before => BEFORE.fetch_add(1, Ordering::SeqCst),
+                Side::After => AFTER.fetch_add(1, Ordering::SeqCst),
+            };
+        }
+
+        let arena = [0u8; 256];
+        let allocator: RedzoneAllocator<_, 8> =
+            RedzoneAllocator::with_report(SliceBumpAllocator::with_slice(arena.as_slice()), report);
+        unsafe {
+            let layout = Layout::from_size_align(12, 4).unwrap();
+            let ptr = allocator.alloc(layout);
+            ptr.add(12).write(0);
+            allocator.dealloc(ptr, layout);
+            assert_eq!(BEFORE.load(Ordering::SeqCst), 0);
+            assert_eq!(AFTER.load(Ordering::SeqCst), 1);
+
+            let ptr = allocator.alloc(layout);
+            ptr.sub(1).write(0);
+            allocator.realloc(ptr, layout, 16);
+            assert_eq!(BEFORE.load(Ordering::SeqCst), 1);
+            assert_eq!(AFTER.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    #[test]
+    fn violation_message() {
+        let violation = Violation {
+            ptr: 0x100 as *const u8,
+            size: 3,
+            side: Side::After,
+        };
+        assert_eq!(
+            std::format!("{}", violation),
+            "Redzone after the 3-byte allocation at 0x100 was overwritten"
+        );
+    }
+}
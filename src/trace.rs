@@ -0,0 +1,557 @@
+/*!
+Capturing allocation events.
+
+[`TracingAllocator`] wraps any other allocator and logs every allocation, deallocation and reallocation into a ring buffer of the last `N` events. The ring buffer is part of the allocator, so tracing never allocates. Events can be logged from multiple threads at the same time. Writers only wait for each other if the ring buffer wraps around to an entry that is still being written. [`TracingAllocator::drain`] encodes all events that haven’t been drained yet into a byte buffer, which can then be written to a file or exported from a WebAssembly module.
+
+```rust
+use silly_alloc::{trace::{self, TracingAllocator}, SliceBumpAllocator};
+use core::alloc::{GlobalAlloc, Layout};
+
+static mut ARENA: [u8; 1024] = [0u8; 1024];
+static ALLOCATOR: TracingAllocator<SliceBumpAllocator, 256> =
+    TracingAllocator::new(SliceBumpAllocator::with_slice(unsafe { ARENA.as_slice() }));
+
+unsafe {
+    let layout = Layout::from_size_align(16, 8).unwrap();
+    let ptr = ALLOCATOR.alloc(layout);
+    ALLOCATOR.dealloc(ptr, layout);
+}
+
+let mut buf = [0u8; 1024];
+trace::write_header(&mut buf);
+let len = trace::HEADER_SIZE + ALLOCATOR.drain(&mut buf[trace::HEADER_SIZE..]);
+
+let mut events = trace::decode(&buf[..len]).unwrap();
+assert_eq!(events.next().unwrap().unwrap().kind, trace::EventKind::Alloc);
+assert_eq!(events.next().unwrap().unwrap().kind, trace::EventKind::Dealloc);
+assert!(events.next().is_none());
+```
+
+# Format
+
+A trace starts with the 4 bytes `SATR`, followed by a single byte with the format version, which is currently [`FORMAT_VERSION`]. After that, events follow back to back. Each event is encoded as a byte for its [`EventKind`], followed by its sequence number, pointer, size and alignment as unsigned LEB128 numbers. Events always use 64-bit numbers, so a trace from a 32-bit WebAssembly module can be decoded on a 64-bit host.
+
+A `realloc` is logged as a [`EventKind::Realloc`] event with the old pointer and size, directly followed by a [`EventKind::Grow`] or [`EventKind::Shrink`] event with the new pointer and size. Reallocations that fail are not logged, while allocations that fail are logged with a null pointer.
+
+The `silly-alloc-replay` tool in this repository replays traces against different allocators, to compare allocator configurations.
+*/
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    fmt::{Debug, Display},
+    hint::spin_loop,
+    sync::atomic::{fence, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+};
+
+/// The bytes every trace starts with.
+pub const MAGIC: [u8; 4] = *b"SATR";
+/// Version of the binary format written by [`write_header`] and [`TracingAllocator::drain`].
+pub const FORMAT_VERSION: u8 = 1;
+/// Size of the header written by [`write_header`].
+pub const HEADER_SIZE: usize = MAGIC.len() + 1;
+/// The biggest size of a single encoded event.
+pub const MAX_EVENT_SIZE: usize = 1 + 4 * 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum EventKind {
+    Alloc = 0,
+    Dealloc = 1,
+    /// The old allocation of a `realloc`.
+    Realloc = 2,
+    /// The new allocation of a `realloc` that made the allocation bigger.
+    Grow = 3,
+    /// The new allocation of a `realloc` that made the allocation smaller or kept its size.
+    Shrink = 4,
+}
+
+impl TryFrom<u8> for EventKind {
+    type Error = DecodeError;
+    fn try_from(value: u8) -> Result<Self, DecodeError> {
+        Ok(match value {
+            0 => EventKind::Alloc,
+            1 => EventKind::Dealloc,
+            2 => EventKind::Realloc,
+            3 => EventKind::Grow,
+            4 => EventKind::Shrink,
+            kind => return Err(DecodeError::InvalidKind(kind)),
+        })
+    }
+}
+
+/// A single logged allocator call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event {
+    /// Position of the event in the order of all events logged by the allocator, starting at 0.
+    pub seq: u64,
+    pub kind: EventKind,
+    pub ptr: u64,
+    pub size: u64,
+    pub align: u64,
+}
+
+impl Event {
+    /// Encodes the event into `buf`, which has to be at least [`MAX_EVENT_SIZE`] bytes long. Returns the number of bytes written.
+    pub fn encode(&self, buf: &mut [u8]) -> usize {
+        buf[0] = self.kind as u8;
+        let mut len = 1;
+        for value in [self.seq, self.ptr, self.size, self.align] {
+            len += encode_leb128(value, &mut buf[len..]);
+        }
+        len
+    }
+
+    /// Decodes an event from the start of `buf`. Returns the event and the number of bytes read.
+    pub fn decode(buf: &[u8]) -> Result<(Event, usize), DecodeError> {
+        let kind = EventKind::try_from(*buf.first().ok_or(DecodeError::UnexpectedEnd)?)?;
+        let mut len = 1;
+        let mut values = [0u64; 4];
+        for value in &mut values {
+            let (v, n) = decode_leb128(&buf[len..])?;
+            *value = v;
+            len += n;
+        }
+        let [seq, ptr, size, align] = values;
+        let event = Event {
+            seq,
+            kind,
+            ptr,
+            size,
+            align,
+        };
+        Ok((event, len))
+    }
+}
+
+fn encode_leb128(mut value: u64, buf: &mut [u8]) -> usize {
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf[len] = byte;
+            return len + 1;
+        }
+        buf[len] = byte | 0x80;
+        len += 1;
+    }
+}
+
+fn decode_leb128(buf: &[u8]) -> Result<(u64, usize), DecodeError> {
+    let mut value = 0u64;
+    for (i, &byte) in buf.iter().enumerate() {
+        let bits = (byte & 0x7f) as u64;
+        if i >= 10 || (i == 9 && bits > 1) {
+            return Err(DecodeError::Overflow);
+        }
+        value |= bits << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+    Err(DecodeError::UnexpectedEnd)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    UnexpectedEnd,
+    /// The trace doesn’t start with [`MAGIC`].
+    InvalidMagic,
+    UnsupportedVersion(u8),
+    InvalidKind(u8),
+    /// A number doesn’t fit into 64 bits.
+    Overflow,
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => write!(f, "Trace ended in the middle of an event"),
+            DecodeError::InvalidMagic => write!(f, "Not an allocation trace"),
+            DecodeError::UnsupportedVersion(v) => write!(f, "Unsupported trace version {}", v),
+            DecodeError::InvalidKind(kind) => write!(f, "Invalid event kind {}", kind),
+            DecodeError::Overflow => write!(f, "Number in trace doesn’t fit into 64 bits"),
+        }
+    }
+}
+
+/// Writes the header a trace has to start with.
+pub fn write_header(buf: &mut [u8]) {
+    buf[..MAGIC.len()].copy_from_slice(&MAGIC);
+    buf[MAGIC.len()] = FORMAT_VERSION;
+}
+
+/// Checks the header of `trace` and returns an iterator over its events.
+pub fn decode(trace: &[u8]) -> Result<Events<'_>, DecodeError> {
+    if trace.len() < HEADER_SIZE {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+    if trace[..MAGIC.len()] != MAGIC {
+        return Err(DecodeError::InvalidMagic);
+    }
+    let version = trace[MAGIC.len()];
+    if version != FORMAT_VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    Ok(Events(&trace[HEADER_SIZE..]))
+}
+
+/// Iterator over the events of a trace, as returned by [`decode`].
+#[derive(Debug, Clone)]
+pub struct Events<'a>(&'a [u8]);
+
+impl Iterator for Events<'_> {
+    type Item = Result<Event, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.is_empty() {
+            return None;
+        }
+        match Event::decode(self.0) {
+            Ok((event, len)) => {
+                self.0 = &self.0[len..];
+                Some(Ok(event))
+            }
+            Err(err) => {
+                // Nothing after a broken event can be decoded.
+                self.0 = &[];
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Value of [`Entry::stamp`] while the entry is being written.
+const BUSY: u64 = u64::MAX;
+
+/// A slot of the ring buffer. The event is stored as separate atomics, so that concurrent writers and readers don’t race. The stamp makes sure a reader never sees a torn event. Like sequence numbers, it is 64 bits wide even on 32-bit targets, so that it can’t wrap around to [`BUSY`] or zero.
+struct Entry {
+    /// Sequence number of the stored event plus one, zero if the entry is empty, or [`BUSY`].
+    stamp: AtomicU64,
+    kind: AtomicU8,
+    ptr: AtomicUsize,
+    size: AtomicUsize,
+    align: AtomicUsize,
+}
+
+impl Entry {
+    const fn new() -> Self {
+        Entry {
+            stamp: AtomicU64::new(0),
+            kind: AtomicU8::new(0),
+            ptr: AtomicUsize::new(0),
+            size: AtomicUsize::new(0),
+            align: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// An allocator that forwards to `A` and logs the last `N` allocator calls into a ring buffer.
+pub struct TracingAllocator<A: GlobalAlloc, const N: usize = 1024> {
+    inner: A,
+    next_seq: AtomicU64,
+    /// Sequence number of the first event that hasn’t been drained.
+    drained_seq: AtomicU64,
+    lost: AtomicUsize,
+    entries: [Entry; N],
+}
+
+impl<A: GlobalAlloc, const N: usize> TracingAllocator<A, N> {
+    pub const fn new(inner: A) -> Self {
+        TracingAllocator {
+            inner,
+            next_seq: AtomicU64::new(0),
+            drained_seq: AtomicU64::new(0),
+            lost: AtomicUsize::new(0),
+            entries: [const { Entry::new() }; N],
+        }
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Returns the number of events that were overwritten before they could be drained.
+    pub fn num_lost(&self) -> usize {
+        self.lost.load(Ordering::Relaxed)
+    }
+
+    fn log(&self, seq: u64, kind: EventKind, ptr: *const u8, size: usize, align: usize) {
+        let entry = &self.entries[(seq % N as u64) as usize];
+        // Claim the entry, so that two writers whose events map to the same entry don’t mix their events.
+        let mut stamp = entry.stamp.load(Ordering::Relaxed);
+        loop {
+            if stamp == BUSY {
+                spin_loop();
+                stamp = entry.stamp.load(Ordering::Relaxed);
+                continue;
+            }
+            if stamp > seq + 1 {
+                // A newer event has already taken the entry, so this one counts as overwritten.
+                return;
+            }
+            match entry.stamp.compare_exchange_weak(
+                stamp,
+                BUSY,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => stamp = current,
+            }
+        }
+        // Readers check the stamp before and after reading the event, so they never see a torn event.
+        fence(Ordering::Release);
+        entry.kind.store(kind as u8, Ordering::Relaxed);
+        entry.ptr.store(ptr as usize, Ordering::Relaxed);
+        entry.size.store(size, Ordering::Relaxed);
+        entry.align.store(align, Ordering::Relaxed);
+        entry.stamp.store(seq + 1, Ordering::Release);
+    }
+
+    fn read(&self, seq: u64) -> Option<Event> {
+        let entry = &self.entries[(seq % N as u64) as usize];
+        if entry.stamp.load(Ordering::Acquire) != seq + 1 {
+            return None;
+        }
+        let kind = entry.kind.load(Ordering::Relaxed);
+        let ptr = entry.ptr.load(Ordering::Relaxed);
+        let size = entry.size.load(Ordering::Relaxed);
+        let align = entry.align.load(Ordering::Relaxed);
+        fence(Ordering::Acquire);
+        if entry.stamp.load(Ordering::Relaxed) != seq + 1 {
+            return None;
+        }
+        Some(Event {
+            seq,
+            kind: EventKind::try_from(kind).ok()?,
+            ptr: ptr as u64,
+            size: size as u64,
+            align: align as u64,
+        })
+    }
+
+    /// Encodes the events that haven’t been drained yet into `buf`, oldest first, and returns the number of bytes written. Events that don’t fit stay in the ring buffer for the next call.
+    ///
+    /// The header is not included, see [`write_header`]. Only one thread should drain at a time.
+    pub fn drain(&self, buf: &mut [u8]) -> usize {
+        let next_seq = self.next_seq.load(Ordering::Acquire);
+        let mut seq = self.drained_seq.load(Ordering::Relaxed);
+        if next_seq - seq > N as u64 {
+            self.lost
+                .fetch_add((next_seq - N as u64 - seq) as usize, Ordering::Relaxed);
+            seq = next_seq - N as u64;
+        }
+        let mut len = 0;
+        let mut scratch = [0u8; MAX_EVENT_SIZE];
+        while seq < next_seq {
+            let Some(event) = self.read(seq) else {
+                // The event is still being written or has already been overwritten.
+                if self.next_seq.load(Ordering::Acquire) - seq > N as u64 {
+                    self.lost.fetch_add(1, Ordering::Relaxed);
+                    seq += 1;
+                    continue;
+                }
+                break;
+            };
+            let event_len = event.encode(&mut scratch);
+            if len + event_len > buf.len() {
+                break;
+            }
+            buf[len..len + event_len].copy_from_slice(&scratch[..event_len]);
+            len += event_len;
+            seq += 1;
+        }
+        self.drained_seq.store(seq, Ordering::Relaxed);
+        len
+    }
+}
+
+impl<A: GlobalAlloc + Debug, const N: usize> Debug for TracingAllocator<A, N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TracingAllocator")
+            .field("inner", &self.inner)
+            .field("events", &self.next_seq.load(Ordering::Relaxed))
+            .field("lost", &self.num_lost())
+            .finish()
+    }
+}
+
+unsafe impl<A: GlobalAlloc + Sync, const N: usize> Sync for TracingAllocator<A, N> {}
+
+unsafe impl<A: GlobalAlloc, const N: usize> GlobalAlloc for TracingAllocator<A, N> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        let seq = self.next_seq.fetch_add(1, Ordering::AcqRel);
+        self.log(seq, EventKind::Alloc, ptr, layout.size(), layout.align());
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout);
+        let seq = self.next_seq.fetch_add(1, Ordering::AcqRel);
+        self.log(seq, EventKind::Dealloc, ptr, layout.size(), layout.align());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        if new_ptr.is_null() {
+            return new_ptr;
+        }
+        // Both events get consecutive sequence numbers, even if other threads log events at the same time.
+        let seq = self.next_seq.fetch_add(2, Ordering::AcqRel);
+        let kind = if new_size > layout.size() {
+            EventKind::Grow
+        } else {
+            EventKind::Shrink
+        };
+        self.log(seq, EventKind::Realloc, ptr, layout.size(), layout.align());
+        self.log(seq + 1, kind, new_ptr, new_size, layout.align());
+        new_ptr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::vec::Vec;
+
+    use crate::SliceBumpAllocator;
+
+    fn drain_all<A: GlobalAlloc, const N: usize>(allocator: &TracingAllocator<A, N>) -> Vec<Event> {
+        let mut buf = [0u8; 1024];
+        write_header(&mut buf);
+        let len = HEADER_SIZE + allocator.drain(&mut buf[HEADER_SIZE..]);
+        decode(&buf[..len])
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn logs_events() {
+        let arena = [0u8; 256];
+        let allocator: TracingAllocator<_, 16> =
+            TracingAllocator::new(SliceBumpAllocator::with_slice(arena.as_slice()));
+        unsafe {
+            let layout = Layout::from_size_align(8, 4).unwrap();
+            let ptr = allocator.alloc(layout);
+            let new_ptr = allocator.realloc(ptr, layout, 16);
+            allocator.dealloc(new_ptr, Layout::from_size_align(16, 4).unwrap());
+
+            let event = |seq, kind, ptr: *mut u8, size| Event {
+                seq,
+                kind,
+                ptr: ptr as usize as u64,
+                size,
+                align: 4,
+            };
+            assert_eq!(
+                drain_all(&allocator),
+                [
+                    event(0, EventKind::Alloc, ptr, 8),
+                    event(1, EventKind::Realloc, ptr, 8),
+                    event(2, EventKind::Grow, new_ptr, 16),
+                    event(3, EventKind::Dealloc, new_ptr, 16),
+                ]
+            );
+            assert_eq!(drain_all(&allocator), []);
+        }
+    }
+
+    #[test]
+    fn ring_buffer() {
+        let arena = [0u8; 256];
+        let allocator: TracingAllocator<_, 4> =
+            TracingAllocator::new(SliceBumpAllocator::with_slice(arena.as_slice()));
+        let layout = Layout::from_size_align(1, 1).unwrap();
+        for _ in 0..6 {
+            unsafe { allocator.alloc(layout) };
+        }
+        let seqs: Vec<u64> = drain_all(&allocator).iter().map(|e| e.seq).collect();
+        assert_eq!(seqs, [2, 3, 4, 5]);
+        assert_eq!(allocator.num_lost(), 2);
+
+        // Events that don’t fit into the buffer are kept for the next drain.
+        unsafe { allocator.alloc(layout) };
+        unsafe { allocator.alloc(layout) };
+        let mut buf = [0u8; 4];
+        assert_eq!(allocator.drain(&mut buf), 0);
+        let seqs: Vec<u64> = drain_all(&allocator).iter().map(|e| e.seq).collect();
+        assert_eq!(seqs, [6, 7]);
+    }
+
+    #[test]
+    fn sequence_numbers_past_usize_max_on_32_bit() {
+        let arena = [0u8; 256];
+        let mut allocator: TracingAllocator<_, 4> =
+            TracingAllocator::new(SliceBumpAllocator::with_slice(arena.as_slice()));
+        let start = u32::MAX as u64 - 2;
+        allocator.next_seq = AtomicU64::new(start);
+        allocator.drained_seq = AtomicU64::new(start);
+        let layout = Layout::from_size_align(1, 1).unwrap();
+        for _ in 0..6 {
+            unsafe { allocator.alloc(layout) };
+        }
+        let seqs: Vec<u64> = drain_all(&allocator).iter().map(|e| e.seq).collect();
+        assert_eq!(seqs, [start + 2, start + 3, start + 4, start + 5]);
+        assert_eq!(allocator.num_lost(), 2);
+    }
+
+    #[test]
+    fn concurrent_writers() {
+        static ALLOCATOR: TracingAllocator<std::alloc::System, 8> =
+            TracingAllocator::new(std::alloc::System);
+        let threads: Vec<_> = (0..4)
+            .map(|t| {
+                std::thread::spawn(move || {
+                    // Size and alignment are the same in every event, so a torn event would show up.
+                    let layout = Layout::from_size_align(8 << t, 8 << t).unwrap();
+                    for _ in 0..1000 {
+                        unsafe { ALLOCATOR.dealloc(ALLOCATOR.alloc(layout), layout) };
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let events = drain_all(&ALLOCATOR);
+        assert_eq!(events.len(), 8);
+        assert!(events.iter().all(|event| event.size == event.align));
+        assert_eq!(ALLOCATOR.num_lost(), 8000 - 8);
+    }
+
+    #[test]
+    fn encoding() {
+        let event = Event {
+            seq: u64::MAX,
+            kind: EventKind::Shrink,
+            ptr: 0x1234_5678_9abc,
+            size: 0,
+            align: 65536,
+        };
+        let mut buf = [0u8; MAX_EVENT_SIZE];
+        let len = event.encode(&mut buf);
+        assert_eq!(Event::decode(&buf[..len]), Ok((event, len)));
+        assert_eq!(
+            Event::decode(&buf[..len - 1]),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        assert_eq!(Event::decode(&[7]), Err(DecodeError::InvalidKind(7)));
+        assert_eq!(
+            Event::decode(&[0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f]),
+            Err(DecodeError::Overflow)
+        );
+
+        assert!(matches!(
+            decode(b"SATR\x02"),
+            Err(DecodeError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            decode(b"ABCD\x01"),
+            Err(DecodeError::InvalidMagic)
+        ));
+    }
+}
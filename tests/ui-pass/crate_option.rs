@@ -0,0 +1,15 @@
+// Stands in for a crate that re-exports silly_alloc under a different path.
+mod facade {
+    pub use silly_alloc as alloc_impl;
+}
+
+use silly_alloc::bucket_allocator;
+
+#[bucket_allocator(crate = crate::facade::alloc_impl)]
+struct MyBucketAllocator {
+    vec4: Bucket<SlotSize<4>, NumSlots<32>, Align<4>>,
+}
+
+fn main() {
+    let _ = MyBucketAllocator::new();
+}
@@ -0,0 +1,10 @@
+use silly_alloc::bucket_allocator;
+
+const ALIGN: usize = 3;
+
+#[bucket_allocator]
+struct MyBucketAllocator {
+    vec3: Bucket<SlotSize<3>, NumSlots<32>, Align<ALIGN>>,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use silly_alloc::bucket_allocator;
+
+#[bucket_allocator]
+struct MyBucketAllocator {
+    vec3: Bucket<SlotSize<3>, NumSlots<32>, Align<3>>,
+}
+
+fn main() {}
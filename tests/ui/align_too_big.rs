@@ -0,0 +1,8 @@
+use silly_alloc::bucket_allocator;
+
+#[bucket_allocator]
+struct MyBucketAllocator {
+    huge: Bucket<SlotSize<131072>, NumSlots<1>, Align<131072>>,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use silly_alloc::bucket_allocator;
+
+#[bucket_allocator]
+struct MyBucketAllocator {
+    vec4: Bucket<SlotSize<4>, NumSlots<32>, SlotSize<8>>,
+}
+
+fn main() {}
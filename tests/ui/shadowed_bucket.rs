@@ -0,0 +1,11 @@
+#![deny(warnings)]
+
+use silly_alloc::bucket_allocator;
+
+#[bucket_allocator]
+struct MyBucketAllocator {
+    vec8: Bucket<SlotSize<8>, NumSlots<32>, Align<8>>,
+    vec4: Bucket<SlotSize<4>, NumSlots<32>, Align<4>>,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use silly_alloc::bucket_allocator;
+
+#[bucket_allocator]
+struct MyBucketAllocator {
+    vec6: Bucket<SlotSize<6>, NumSlots<32>, Align<4>>,
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use silly_alloc::bucket::Zeroable;
+use silly_alloc::bucket_allocator;
+
+#[bucket_allocator(stats = true)]
+struct MyBucketAllocator {
+    vec4: Bucket<SlotSize<4>, NumSlots<32>, Align<4>>,
+}
+
+fn main() {
+    let _allocator = <MyBucketAllocator as Zeroable>::zeroed();
+}
@@ -0,0 +1,10 @@
+use silly_alloc::bucket_allocator;
+
+const NUM_SLOTS: usize = 0;
+
+#[bucket_allocator]
+struct MyBucketAllocator {
+    empty: Bucket<SlotSize<4>, NumSlots<NUM_SLOTS>, Align<4>>,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use silly_alloc::bucket_allocator;
+
+#[bucket_allocator]
+struct MyBucketAllocator {
+    empty: Bucket<SlotSize<4>, NumSlots<0>, Align<4>>,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use silly_alloc::bucket_allocator;
+
+#[bucket_allocator]
+struct MyBucketAllocator {
+    empty: Bucket<SlotSize<0>, NumSlots<32>, Align<1>>,
+}
+
+fn main() {}